@@ -50,15 +50,168 @@ pub fn final_z_wires_value(input: &str) -> u64 {
 }
 
 pub fn ripple_carry_adder_swapped_wires(input: &str) -> String {
+    // Retrieve sorted swapped wires names from verified swap pairs, searching them if suspects fail
+    ripple_carry_adder_swap_pairs(input)
+        .or_else(|| function_swap_pairs(input, CircuitFunction::Add, 4))
+        .expect("Expected 4 swap pairs to repair the adder")
+        .into_iter()
+        .flat_map(<[String; 2]>::from)
+        .sorted_unstable()
+        .join(",")
+}
+
+pub fn ripple_carry_adder_swap_pairs(input: &str) -> Option<Vec<(String, String)>> {
+    // Try every pairing of suspect wires until the adder is repaired
+    fn pair_suspects(
+        circuit: &mut Circuit,
+        suspects: &mut Vec<usize>,
+        pairs: &mut Vec<(usize, usize)>,
+    ) -> bool {
+        let Some(first) = suspects.pop() else {
+            return circuit.computes(CircuitFunction::Add);
+        };
+
+        for i in 0..suspects.len() {
+            let second = suspects.swap_remove(i);
+
+            circuit.swap_outputs(first, second);
+            pairs.push((first, second));
+
+            if pair_suspects(circuit, suspects, pairs) {
+                return true;
+            }
+
+            pairs.pop();
+            circuit.swap_outputs(first, second);
+
+            suspects.push(second);
+            let last = suspects.len() - 1;
+            suspects.swap(i, last);
+        }
+
+        suspects.push(first);
+        false
+    }
+
     let (_, gates) = circuit_inputs_and_gates(input);
+    let suspects = ripple_carry_adder_suspect_wires(&gates);
+    let mut circuit = Circuit::new(&gates);
+
+    let mut suspect_indices = suspects
+        .iter()
+        .map(|&wire| circuit.wire(wire))
+        .collect_vec();
+    let mut pairs = Vec::with_capacity(suspects.len() / 2);
 
+    // Pairings grow factorially, so only try up to the 4 swaps expected by the puzzle
+    if suspects.len() > 8
+        || !suspects.len().is_multiple_of(2)
+        || !pair_suspects(&mut circuit, &mut suspect_indices, &mut pairs)
+    {
+        return None;
+    }
+
+    // Retrieve swap pairs names
+    Some(
+        pairs
+            .into_iter()
+            .map(|(wire_a, wire_b)| circuit.sorted_names(wire_a, wire_b))
+            .sorted_unstable()
+            .collect(),
+    )
+}
+
+pub fn function_swap_pairs(
+    input: &str,
+    function: CircuitFunction,
+    swaps_count: usize,
+) -> Option<Vec<(String, String)>> {
+    // Recursive search accepting only swaps that move the first failure to a higher bit, or reduce
+    // its failing test cases, so it cannot repair circuits where every remaining swap on its own
+    // leaves the first failure in place or moves it lower
+    fn search_swaps(
+        circuit: &mut Circuit,
+        function: CircuitFunction,
+        swaps_left: usize,
+        pairs: &mut Vec<(usize, usize)>,
+    ) -> bool {
+        let Some((bit, wrong_outputs, wrong_cases)) = circuit.first_failure(function) else {
+            return true;
+        };
+
+        if swaps_left == 0 {
+            return false;
+        }
+
+        // One swapped wire must feed the wrong outputs, and neither may feed lower correct outputs
+        // nor be already swapped
+        let correct_dependencies = circuit.dependencies((1 << bit) - 1);
+        let wrong_dependencies = circuit.dependencies(wrong_outputs);
+        let candidates = (0..circuit.names.len())
+            .filter(|&wire| {
+                circuit.gates[wire].is_some()
+                    && !correct_dependencies[wire]
+                    && pairs
+                        .iter()
+                        .all(|&(wire_a, wire_b)| wire != wire_a && wire != wire_b)
+            })
+            .collect_vec();
+
+        for &wire_a in candidates.iter().filter(|&&wire| wrong_dependencies[wire]) {
+            for &wire_b in &candidates {
+                if wire_b == wire_a || wrong_dependencies[wire_b] && wire_b < wire_a {
+                    continue;
+                }
+
+                circuit.swap_outputs(wire_a, wire_b);
+
+                if circuit
+                    .first_failure(function)
+                    .is_none_or(|(next_bit, _, next_wrong_cases)| {
+                        next_bit > bit || next_bit == bit && next_wrong_cases < wrong_cases
+                    })
+                {
+                    pairs.push((wire_a, wire_b));
+
+                    if search_swaps(circuit, function, swaps_left - 1, pairs) {
+                        return true;
+                    }
+
+                    pairs.pop();
+                }
+
+                circuit.swap_outputs(wire_a, wire_b);
+            }
+        }
+
+        false
+    }
+
+    let (_, gates) = circuit_inputs_and_gates(input);
+    let mut circuit = Circuit::new(&gates);
+    let mut pairs = Vec::with_capacity(swaps_count);
+
+    // Search swaps and retrieve swap pairs names
+    search_swaps(&mut circuit, function, swaps_count, &mut pairs).then(|| {
+        pairs
+            .into_iter()
+            .map(|(wire_a, wire_b)| circuit.sorted_names(wire_a, wire_b))
+            .sorted_unstable()
+            .collect()
+    })
+}
+
+// ------------------------------------------------------------------------------------------------
+// Functions
+
+fn ripple_carry_adder_suspect_wires(gates: &Gates) -> Vec<[u8; 3]> {
     let (mut or_inputs, mut xor_inputs) =
         (FxHashSet::with_capacity(96), FxHashSet::with_capacity(192));
 
     let mut z_msb = b"z00";
 
     // Prepare or/xor inputs and find z wires most significant bit
-    for (output, (operation, input_a, input_b)) in &gates {
+    for (output, (operation, input_a, input_b)) in gates {
         match operation {
             0 => {}
             1 => {
@@ -77,10 +230,10 @@ pub fn ripple_carry_adder_swapped_wires(input: &str) -> String {
         }
     }
 
-    let mut swapped_wires = Vec::with_capacity(8);
+    let mut suspect_wires = Vec::with_capacity(8);
 
-    // Find swapped wires by enforcing puzzle input constraints
-    for (output, (operation, input_a, input_b)) in &gates {
+    // Find suspect wires by enforcing puzzle input constraints
+    for (output, (operation, input_a, input_b)) in gates {
         match operation {
             0 => {
                 // AND cannot output z wires
@@ -88,40 +241,283 @@ pub fn ripple_carry_adder_swapped_wires(input: &str) -> String {
                 if output[0] == b'z'
                     || input_a != b"x00" && input_a != b"y00" && !or_inputs.contains(&output)
                 {
-                    swapped_wires.push(output);
+                    suspect_wires.push(*output);
                 }
             }
             1 => {
                 // OR cannot output z wires, unless last full adder
                 if output[0] == b'z' && output != z_msb {
-                    swapped_wires.push(output);
+                    suspect_wires.push(*output);
                 }
             }
             2 => match (input_a[0], input_b[0], output[0]) {
                 (b'x', b'y', _) | (b'y', b'x', _) => {
                     // XOR output must be second XOR input, unless half adder
                     if output != b"z00" && !xor_inputs.contains(&output) {
-                        swapped_wires.push(output);
+                        suspect_wires.push(*output);
                     }
                 }
                 // XOR output can be z if second gate
                 (_, _, b'z') => {}
                 // All other cases are wrong
-                _ => swapped_wires.push(output),
+                _ => suspect_wires.push(*output),
             },
             _ => unreachable!("Unknown gate operation"),
         }
     }
 
-    // Retrieve swapped wires names
-    swapped_wires.sort_unstable();
-    Itertools::intersperse(
-        swapped_wires
-            .into_iter()
-            .map(|wire| String::from_utf8_lossy(wire).into_owned()),
-        ",".to_string(),
-    )
-    .collect()
+    suspect_wires.sort_unstable();
+    suspect_wires
+}
+
+// ------------------------------------------------------------------------------------------------
+// Circuit
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub enum CircuitFunction {
+    Add,
+    And,
+    Sub,
+}
+
+impl CircuitFunction {
+    const fn apply(self, x: u64, y: u64) -> u64 {
+        match self {
+            Self::Add => x.wrapping_add(y),
+            Self::And => x & y,
+            Self::Sub => x.wrapping_sub(y),
+        }
+    }
+
+    // Test cases (bit, (x, y)) exercising each bit position, with the one below it if carrying
+    fn test_cases(self, bits: usize) -> Vec<(usize, (u64, u64))> {
+        let (combinations, chain) = match self {
+            Self::Add => (16, Some((u64::MAX >> (64 - bits), 1))),
+            Self::And => (4, None),
+            Self::Sub => (16, Some((0, 1))),
+        };
+
+        (0..bits)
+            .flat_map(|bit| {
+                let lower = bit.saturating_sub(1);
+
+                (0..combinations).map(move |combination: u64| {
+                    (
+                        bit,
+                        (
+                            (combination & 1) << bit | (combination >> 2 & 1) << lower,
+                            (combination >> 1 & 1) << bit | (combination >> 3 & 1) << lower,
+                        ),
+                    )
+                })
+            })
+            .chain(chain.map(|test_case| (bits, test_case)))
+            .collect()
+    }
+}
+
+#[derive(Clone)]
+struct Circuit {
+    names: Vec<[u8; 3]>,
+    indices: FxHashMap<[u8; 3], usize>,
+    gates: Vec<Option<(u8, usize, usize)>>,
+    x_wires: Vec<usize>,
+    y_wires: Vec<usize>,
+    z_wires: Vec<usize>,
+}
+
+impl Circuit {
+    fn new(gates: &Gates) -> Self {
+        // Index all wire names in sorted order
+        let names = gates
+            .iter()
+            .flat_map(|(&output, &(_, input_a, input_b))| [output, input_a, input_b])
+            .sorted_unstable()
+            .dedup()
+            .collect_vec();
+        let indices: FxHashMap<_, _> = names
+            .iter()
+            .enumerate()
+            .map(|(index, &name)| (name, index))
+            .collect();
+
+        let mut circuit_gates = vec![None; names.len()];
+
+        for (output, &(operation, input_a, input_b)) in gates {
+            circuit_gates[indices[output]] =
+                Some((operation, indices[&input_a], indices[&input_b]));
+        }
+
+        let prefix_wires = |prefix| {
+            names
+                .iter()
+                .enumerate()
+                .filter(|&(_, name)| name[0] == prefix)
+                .map(|(index, _)| index)
+                .collect_vec()
+        };
+
+        Self {
+            x_wires: prefix_wires(b'x'),
+            y_wires: prefix_wires(b'y'),
+            z_wires: prefix_wires(b'z'),
+            names,
+            indices,
+            gates: circuit_gates,
+        }
+    }
+
+    fn wire(&self, name: [u8; 3]) -> usize {
+        *self.indices.get(&name).expect("Wire not found")
+    }
+
+    fn sorted_names(&self, wire_a: usize, wire_b: usize) -> (String, String) {
+        let [name_a, name_b] =
+            [wire_a, wire_b].map(|wire| String::from_utf8_lossy(&self.names[wire]).into_owned());
+
+        if name_a < name_b {
+            (name_a, name_b)
+        } else {
+            (name_b, name_a)
+        }
+    }
+
+    fn swap_outputs(&mut self, wire_a: usize, wire_b: usize) {
+        self.gates.swap(wire_a, wire_b);
+    }
+
+    // Bit-sliced evaluation of up to 64 test cases at once, None if the circuit has loops
+    fn evaluate(&self, test_cases: &[(u64, u64)]) -> Option<Vec<u64>> {
+        fn wire_value(
+            circuit: &Circuit,
+            wire: usize,
+            values: &mut [u64],
+            states: &mut [u8],
+        ) -> Option<u64> {
+            match states[wire] {
+                2 => return Some(values[wire]),
+                1 => return None,
+                _ => {}
+            }
+
+            let (operation, input_a, input_b) = circuit.gates[wire]?;
+
+            states[wire] = 1;
+
+            let a_value = wire_value(circuit, input_a, values, states)?;
+            let b_value = wire_value(circuit, input_b, values, states)?;
+
+            values[wire] = match operation {
+                0 => a_value & b_value,
+                1 => a_value | b_value,
+                2 => a_value ^ b_value,
+                _ => unreachable!("Unknown gate operation"),
+            };
+            states[wire] = 2;
+
+            Some(values[wire])
+        }
+
+        let (mut values, mut states) = (vec![0; self.names.len()], vec![0; self.names.len()]);
+
+        // Slice x and y inputs across test cases
+        for (wires, select) in [
+            (&self.x_wires, (|&(x, _)| x) as fn(&(u64, u64)) -> u64),
+            (&self.y_wires, |&(_, y)| y),
+        ] {
+            for (bit, &wire) in wires.iter().enumerate() {
+                values[wire] = test_cases
+                    .iter()
+                    .enumerate()
+                    .fold(0, |acc, (case, test_case)| {
+                        acc | (select(test_case) >> bit & 1) << case
+                    });
+                states[wire] = 2;
+            }
+        }
+
+        // Gather z outputs back into test cases values
+        let mut outputs = vec![0; test_cases.len()];
+
+        for (bit, &wire) in self.z_wires.iter().enumerate() {
+            let value = wire_value(self, wire, &mut values, &mut states)?;
+
+            for (case, output) in outputs.iter_mut().enumerate() {
+                *output |= (value >> case & 1) << bit;
+            }
+        }
+
+        Some(outputs)
+    }
+
+    // First failing test case bit, its wrong z outputs and the failing test cases count of that
+    // bit, None if the circuit computes the function
+    fn first_failure(&self, function: CircuitFunction) -> Option<(usize, u64, usize)> {
+        let mask = u64::MAX >> (64 - self.z_wires.len());
+
+        // Check all test cases in chunks of 64, holding whole bits
+        function
+            .test_cases(self.x_wires.len())
+            .chunks(64)
+            .find_map(|test_cases| {
+                let cases = test_cases
+                    .iter()
+                    .map(|&(_, test_case)| test_case)
+                    .collect_vec();
+
+                // Loops make every output of the chunk wrong
+                self.evaluate(&cases).map_or(
+                    Some((test_cases[0].0, mask, test_cases.len())),
+                    |outputs| {
+                        let mut failures = test_cases.iter().zip(outputs).filter_map(
+                            |(&(bit, (x, y)), output)| {
+                                let wrong_outputs = (function.apply(x, y) & mask) ^ output;
+
+                                (wrong_outputs != 0).then_some((bit, wrong_outputs))
+                            },
+                        );
+
+                        failures.next().map(|(bit, wrong_outputs)| {
+                            (
+                                bit,
+                                wrong_outputs,
+                                1 + failures
+                                    .take_while(|&(next_bit, _)| next_bit == bit)
+                                    .count(),
+                            )
+                        })
+                    },
+                )
+            })
+    }
+
+    fn computes(&self, function: CircuitFunction) -> bool {
+        self.first_failure(function).is_none()
+    }
+
+    // Gate wires the given z outputs depend on
+    fn dependencies(&self, z_outputs: u64) -> Vec<bool> {
+        let mut dependencies = vec![false; self.names.len()];
+        let mut stack = self
+            .z_wires
+            .iter()
+            .enumerate()
+            .filter(|&(bit, _)| z_outputs >> bit & 1 == 1)
+            .map(|(_, &wire)| wire)
+            .collect_vec();
+
+        while let Some(wire) = stack.pop() {
+            if let Some((_, input_a, input_b)) = self.gates[wire]
+                && !dependencies[wire]
+            {
+                dependencies[wire] = true;
+                stack.extend([input_a, input_b]);
+            }
+        }
+
+        dependencies
+    }
 }
 
 // ------------------------------------------------------------------------------------------------
@@ -166,3 +562,141 @@ fn circuit_inputs_and_gates(input: &str) -> (Inputs, Gates) {
             .collect(),
     )
 }
+
+// ------------------------------------------------------------------------------------------------
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ripple_carry_adder(bits: usize, swaps: &[(&str, &str)]) -> String {
+        let mut gates = vec![
+            ("x00".to_owned(), "XOR", "y00".to_owned(), "z00".to_owned()),
+            ("x00".to_owned(), "AND", "y00".to_owned(), "d00".to_owned()),
+        ];
+
+        for bit in 1..bits {
+            let (x, y, carry) = (
+                format!("x{bit:02}"),
+                format!("y{bit:02}"),
+                format!("d{:02}", bit - 1),
+            );
+            let carry_out = if bit == bits - 1 {
+                format!("z{bits:02}")
+            } else {
+                format!("d{bit:02}")
+            };
+
+            gates.extend([
+                (x.clone(), "XOR", y.clone(), format!("a{bit:02}")),
+                (
+                    format!("a{bit:02}"),
+                    "XOR",
+                    carry.clone(),
+                    format!("z{bit:02}"),
+                ),
+                (x, "AND", y, format!("b{bit:02}")),
+                (format!("a{bit:02}"), "AND", carry, format!("c{bit:02}")),
+                (format!("b{bit:02}"), "OR", format!("c{bit:02}"), carry_out),
+            ]);
+        }
+
+        // Swap gate outputs
+        for (_, _, _, output) in &mut gates {
+            if let Some(&(wire_a, wire_b)) = swaps
+                .iter()
+                .find(|&&(wire_a, wire_b)| output == wire_a || output == wire_b)
+            {
+                *output = if output == wire_a { wire_b } else { wire_a }.to_owned();
+            }
+        }
+
+        (0..bits)
+            .map(|bit| format!("x{bit:02}: 0"))
+            .chain((0..bits).map(|bit| format!("y{bit:02}: 0")))
+            .join("\n")
+            + "\n\n"
+            + &gates
+                .iter()
+                .map(|(input_a, operation, input_b, output)| {
+                    format!("{input_a} {operation} {input_b} -> {output}")
+                })
+                .join("\n")
+    }
+
+    #[test]
+    fn swap_search_repairs_generated_adders() {
+        for swaps in [
+            [
+                ("z02", "b02"),
+                ("a05", "b05"),
+                ("z09", "d09"),
+                ("c12", "z12"),
+            ],
+            [
+                ("a09", "c03"),
+                ("d12", "z00"),
+                ("d03", "b12"),
+                ("a13", "z02"),
+            ],
+            [
+                ("z00", "c05"),
+                ("z07", "c15"),
+                ("b04", "d03"),
+                ("z01", "c06"),
+            ],
+        ] {
+            let pairs =
+                function_swap_pairs(&ripple_carry_adder(16, &swaps), CircuitFunction::Add, 4)
+                    .expect("Expected adder to be repaired");
+
+            assert_eq!(
+                pairs,
+                swaps
+                    .map(|(wire_a, wire_b)| (
+                        wire_a.min(wire_b).to_owned(),
+                        wire_a.max(wire_b).to_owned()
+                    ))
+                    .into_iter()
+                    .sorted_unstable()
+                    .collect_vec()
+            );
+        }
+    }
+
+    #[test]
+    fn swapped_wires_fall_back_to_swap_search() {
+        let input = ripple_carry_adder(
+            16,
+            &[
+                ("z02", "b02"),
+                ("a05", "b05"),
+                ("z09", "d09"),
+                ("c12", "z12"),
+            ],
+        );
+
+        assert!(ripple_carry_adder_swap_pairs(&input).is_some());
+        assert_eq!(
+            ripple_carry_adder_swapped_wires(&input),
+            "a05,b02,b05,c12,d09,z02,z09,z12"
+        );
+
+        let input = ripple_carry_adder(
+            16,
+            &[
+                ("a09", "c03"),
+                ("d12", "z00"),
+                ("d03", "b12"),
+                ("a13", "z02"),
+            ],
+        );
+
+        assert!(ripple_carry_adder_swap_pairs(&input).is_none());
+        assert_eq!(
+            ripple_carry_adder_swapped_wires(&input),
+            "a09,a13,b12,c03,d03,d12,z00,z02"
+        );
+    }
+}
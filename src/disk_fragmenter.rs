@@ -1,19 +1,18 @@
-use std::{cmp::Reverse, collections::BinaryHeap};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap},
+};
 
-use itertools::Itertools;
+use itertools::{Either, Itertools};
+
+use crate::random_utils::parse_numbers;
 
 // ------------------------------------------------------------------------------------------------
 // Exports
 
 pub fn compact_disk_checksum(input: &str) -> usize {
     // Create vector of disk blocks as Options
-    let disk = input
-        .trim()
-        .bytes()
-        .enumerate()
-        .filter(|&(_, c)| c != b'0')
-        .flat_map(|(i, c)| vec![if i % 2 == 0 { Some(i / 2) } else { None }; (c & 0xf) as usize])
-        .collect_vec();
+    let disk = disk_blocks(input);
 
     let (mut start, mut end) = (0, disk.len() - 1);
     let mut checksum = 0;
@@ -45,6 +44,11 @@ pub fn whole_files_compact_disk_checksum(input: &str) -> usize {
     // Offset precomputed triangle numbers for checksum calculation
     const TRIANGLE: [usize; 10] = [0, 0, 1, 3, 6, 10, 15, 21, 28, 36];
 
+    // Comma separated sizes and empty files merging free spaces need the generalized algorithm
+    if input.contains(',') || input.trim().bytes().step_by(2).any(|c| c == b'0') {
+        return wide_whole_files_compact_disk_checksum(input);
+    }
+
     let mut free_space = vec![BinaryHeap::with_capacity(input.len() / 16); 10];
     let mut block = 0;
 
@@ -65,14 +69,14 @@ pub fn whole_files_compact_disk_checksum(input: &str) -> usize {
         })
         .collect_vec();
 
-    let (parity, mut checksum) = (disk.len() % 2, 0);
+    let mut checksum = 0;
 
     // Calculate checksum by moving blocks to leftmost free space while keeping min-heaps updated
     for (block_index, block_size) in disk.into_iter().enumerate().rev() {
         block -= block_size;
 
-        // Skip empty blocks
-        if block_index % 2 == parity {
+        // Skip free space, at odd indices whether or not the map ends with a file
+        if block_index % 2 == 1 {
             continue;
         }
 
@@ -116,3 +120,513 @@ pub fn whole_files_compact_disk_checksum(input: &str) -> usize {
 
     checksum
 }
+
+#[allow(dead_code)]
+pub fn compact_disk_layout(input: &str) -> Vec<Option<usize>> {
+    // Block by block compaction layout
+    compact_disk(input, &BlockByBlock).layout
+}
+
+#[allow(dead_code)]
+pub fn whole_files_compact_disk_layout(input: &str) -> Vec<Option<usize>> {
+    // Whole files leftmost fit compaction layout
+    compact_disk(input, &FirstFit).layout
+}
+
+#[allow(dead_code)]
+pub struct Compaction {
+    pub layout: Vec<Option<usize>>,
+    pub checksum: usize,
+    pub file_fragments: usize,
+    pub fragmented_files: usize,
+    pub free_gaps: usize,
+}
+
+pub fn compact_disk<S>(input: &str, strategy: &S) -> Compaction
+where
+    S: CompactionStrategy,
+{
+    let mut layout = disk_blocks(input);
+
+    strategy.compact(&mut layout);
+
+    // Count contiguous runs of every file and free gaps between file blocks
+    let runs = layout.iter().dedup().collect_vec();
+    let file_runs = runs.iter().filter_map(|&&block| block).counts();
+    let last_file_run = runs.iter().rposition(|block| block.is_some());
+
+    Compaction {
+        checksum: disk_layout_checksum(&layout),
+        file_fragments: file_runs.values().sum(),
+        fragmented_files: file_runs.values().filter(|&&runs| runs > 1).count(),
+        free_gaps: last_file_run.map_or(0, |last| {
+            runs[..last].iter().filter(|block| block.is_none()).count()
+        }),
+        layout,
+    }
+}
+
+pub trait CompactionStrategy {
+    fn compact(&self, disk: &mut [Option<usize>]);
+}
+
+pub struct BlockByBlock;
+
+impl CompactionStrategy for BlockByBlock {
+    fn compact(&self, disk: &mut [Option<usize>]) {
+        let (mut start, mut end) = (0, disk.len().saturating_sub(1));
+
+        // Use two pointers to swap blocks into leftmost free space
+        while start < end {
+            if disk[start].is_some() {
+                start += 1;
+            } else if disk[end].is_none() {
+                end -= 1;
+            } else {
+                disk.swap(start, end);
+            }
+        }
+    }
+}
+
+pub struct FirstFit;
+
+impl CompactionStrategy for FirstFit {
+    fn compact(&self, disk: &mut [Option<usize>]) {
+        // Leftmost free span where file fits
+        move_whole_files(disk, |free_spans| free_spans.next().map(|(index, _)| index));
+    }
+}
+
+#[allow(dead_code)]
+pub struct BestFit;
+
+impl CompactionStrategy for BestFit {
+    fn compact(&self, disk: &mut [Option<usize>]) {
+        // Smallest free span where file fits, leftmost if tied
+        move_whole_files(disk, |free_spans| {
+            free_spans
+                .min_by_key(|&(_, (_, free_size))| free_size)
+                .map(|(index, _)| index)
+        });
+    }
+}
+
+#[allow(dead_code)]
+pub struct WorstFit;
+
+impl CompactionStrategy for WorstFit {
+    fn compact(&self, disk: &mut [Option<usize>]) {
+        // Biggest free span where file fits, leftmost if tied
+        move_whole_files(disk, |free_spans| {
+            free_spans
+                .max_by_key(|&(index, (_, free_size))| (free_size, Reverse(index)))
+                .map(|(index, _)| index)
+        });
+    }
+}
+
+#[allow(dead_code)]
+pub struct UntilStable<S>(pub S);
+
+impl<S> CompactionStrategy for UntilStable<S>
+where
+    S: CompactionStrategy,
+{
+    fn compact(&self, disk: &mut [Option<usize>]) {
+        let mut previous_disk = Vec::with_capacity(disk.len());
+
+        // Repeat inner strategy passes until nothing moves
+        while previous_disk != disk {
+            previous_disk = disk.to_vec();
+            self.0.compact(disk);
+        }
+    }
+}
+
+#[allow(dead_code)]
+pub struct Defragment;
+
+impl CompactionStrategy for Defragment {
+    fn compact(&self, disk: &mut [Option<usize>]) {
+        // Files sizes in order of first block
+        let files = disk.iter().flatten().copied().counts();
+        let files_order = disk.iter().flatten().unique().copied().collect_vec();
+
+        // Pack every file contiguously from the start, keeping files order
+        disk.fill(None);
+
+        let mut block = 0;
+
+        for file in files_order {
+            let size = files[&file];
+
+            disk[block..block + size].fill(Some(file));
+            block += size;
+        }
+    }
+}
+
+#[allow(dead_code)]
+pub fn disk_layout_checksum(disk: &[Option<usize>]) -> usize {
+    // Sum of block positions times file ids
+    disk.iter()
+        .enumerate()
+        .filter_map(|(position, &block)| block.map(|file| position * file))
+        .sum()
+}
+
+#[allow(dead_code)]
+pub fn disk_layout_string(disk: &[Option<usize>]) -> String {
+    // File ids for file blocks and dots for free blocks, as in the puzzle examples
+    let mut blocks = disk
+        .iter()
+        .map(|&block| block.map_or_else(|| ".".to_string(), |file| file.to_string()));
+
+    // Comma separated blocks when multi-digit ids would be ambiguous
+    if disk.iter().flatten().any(|&file| file > 9) {
+        blocks.join(",")
+    } else {
+        blocks.collect()
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Functions
+
+fn wide_whole_files_compact_disk_checksum(input: &str) -> usize {
+    let mut free_spans: Vec<(usize, usize)> = Vec::new();
+    let mut block = 0;
+
+    // Collect free spans, merging the ones only separated by empty files, and file block sizes
+    let disk = disk_map_sizes(input)
+        .enumerate()
+        .map(|(i, size)| {
+            if i % 2 == 1 && size > 0 {
+                match free_spans.last_mut() {
+                    Some((start, span_size)) if *start + *span_size == block => {
+                        *span_size += size;
+                    }
+                    _ => free_spans.push((block, size)),
+                }
+            }
+
+            block += size;
+            size
+        })
+        .collect_vec();
+
+    // Fill free space min-heaps for every free size
+    let mut free_space: BTreeMap<usize, BinaryHeap<Reverse<usize>>> = BTreeMap::new();
+
+    for (start, size) in free_spans {
+        free_space.entry(size).or_default().push(Reverse(start));
+    }
+
+    let mut checksum = 0;
+
+    // Calculate checksum by moving blocks to leftmost free space while keeping min-heaps updated
+    for (block_index, block_size) in disk.into_iter().enumerate().rev() {
+        block -= block_size;
+
+        // Skip free space, at odd indices whether or not the map ends with a file, and empty files
+        if block_index % 2 == 1 || block_size == 0 {
+            continue;
+        }
+
+        // Find leftmost free space where block fits
+        let best = free_space
+            .range(block_size..)
+            .filter_map(|(&size, indices)| indices.peek().map(|&Reverse(index)| (index, size)))
+            .filter(|&(index, _)| index < block)
+            .min();
+
+        // Remove biggest free spaces with smallest index to the right of current
+        if let Some(biggest) = free_space.last_entry()
+            && biggest
+                .get()
+                .peek()
+                .is_none_or(|&Reverse(first)| first > block)
+        {
+            biggest.remove();
+        }
+
+        // Update checksum with factorized formula
+        let best_index = best.map_or(block, |(index, _)| index);
+
+        checksum += block_index / 2
+            * (best_index * block_size + block_size * block_size.saturating_sub(1) / 2);
+
+        // Update free spaces
+        if let Some((best_index, best_size)) = best {
+            free_space
+                .get_mut(&best_size)
+                .expect("Expected free space size")
+                .pop();
+
+            let new_size = best_size - block_size;
+
+            if new_size > 0 {
+                free_space
+                    .entry(new_size)
+                    .or_default()
+                    .push(Reverse(best_index + block_size));
+            }
+        }
+    }
+
+    checksum
+}
+
+fn move_whole_files<Fit>(disk: &mut [Option<usize>], fit: Fit)
+where
+    Fit: Fn(&mut dyn Iterator<Item = (usize, (usize, usize))>) -> Option<usize>,
+{
+    // Contiguous file runs and free spans as (start block, size)
+    let (mut file_runs, mut free_spans) = (Vec::new(), Vec::new());
+
+    let mut start = 0;
+
+    for (size, &block) in disk.iter().dedup_with_count() {
+        match block {
+            Some(file) => file_runs.push((file, start, size)),
+            None => free_spans.push((start, size)),
+        }
+
+        start += size;
+    }
+
+    // Move every file run once to a free span to its left chosen by fit, highest file id first
+    file_runs.sort_unstable_by_key(|&(file, start, _)| Reverse((file, start)));
+
+    for (file, file_start, file_size) in file_runs {
+        let Some(index) = fit(&mut free_spans
+            .iter()
+            .copied()
+            .enumerate()
+            .take_while(|&(_, (free_start, _))| free_start < file_start)
+            .filter(|&(_, (_, free_size))| free_size >= file_size))
+        else {
+            continue;
+        };
+
+        let (free_start, free_size) = free_spans[index];
+
+        disk[free_start..free_start + file_size].fill(Some(file));
+        disk[file_start..file_start + file_size].fill(None);
+
+        // Shrink used free span and merge vacated file run into free spans
+        if free_size == file_size {
+            free_spans.remove(index);
+        } else {
+            free_spans[index] = (free_start + file_size, free_size - file_size);
+        }
+
+        let index = free_spans.partition_point(|&(start, _)| start < file_start);
+        let mut size = file_size;
+
+        if let Some(&(next_start, next_size)) = free_spans.get(index)
+            && next_start == file_start + size
+        {
+            size += next_size;
+            free_spans.remove(index);
+        }
+
+        match index.checked_sub(1) {
+            Some(previous) if free_spans[previous].0 + free_spans[previous].1 == file_start => {
+                free_spans[previous].1 += size;
+            }
+            _ => free_spans.insert(index, (file_start, size)),
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Parsers
+
+fn disk_map_sizes(input: &str) -> impl Iterator<Item = usize> + '_ {
+    // Alternating file and free space sizes, either single digits or comma separated
+    if input.contains(',') {
+        Either::Left(parse_numbers(input))
+    } else {
+        Either::Right(input.trim().bytes().map(|c| (c & 0xf) as usize))
+    }
+}
+
+fn disk_blocks(input: &str) -> Vec<Option<usize>> {
+    // Create vector of disk blocks as Options
+    disk_map_sizes(input)
+        .enumerate()
+        .flat_map(|(i, size)| vec![if i % 2 == 0 { Some(i / 2) } else { None }; size])
+        .collect()
+}
+
+// ------------------------------------------------------------------------------------------------
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout_checksum(input: &str) -> usize {
+        disk_layout_checksum(&whole_files_compact_disk_layout(input))
+    }
+
+    #[test]
+    fn empty_files_merge_free_spans() {
+        for input in [
+            "1,1,0,1,2",
+            "11012",
+            "2,1,0,0,0,2,3",
+            "3,1,0,1,0,1,2",
+            "1,3,0,2,5",
+        ] {
+            assert_eq!(
+                whole_files_compact_disk_checksum(input),
+                layout_checksum(input)
+            );
+        }
+
+        assert_eq!(whole_files_compact_disk_checksum("1,1,0,1,2"), 6);
+    }
+
+    #[test]
+    fn trailing_free_space_is_ignored() {
+        for input in ["1,2,3,4", "2333133121414131402", "1234", "12,3,1,5,2,7"] {
+            assert_eq!(
+                whole_files_compact_disk_checksum(input),
+                layout_checksum(input)
+            );
+        }
+    }
+
+    #[test]
+    fn compaction_strategies_layouts_and_metrics() {
+        fn compaction<S>(input: &str, strategy: &S) -> (Vec<Option<usize>>, [usize; 4])
+        where
+            S: CompactionStrategy,
+        {
+            let compaction = compact_disk(input, strategy);
+
+            (
+                compaction.layout,
+                [
+                    compaction.checksum,
+                    compaction.file_fragments,
+                    compaction.fragmented_files,
+                    compaction.free_gaps,
+                ],
+            )
+        }
+
+        let blocks = |layout: &str| {
+            layout
+                .bytes()
+                .map(|b| b.is_ascii_digit().then(|| (b - b'0') as usize))
+                .collect_vec()
+        };
+        let input = "2333133121414131402";
+
+        for (layout, metrics, compacted) in [
+            (
+                "0099811188827773336446555566..............",
+                [1928, 13, 2, 0],
+                compaction(input, &BlockByBlock),
+            ),
+            (
+                "00992111777.44.333....5555.6666.....8888..",
+                [2858, 10, 0, 5],
+                compaction(input, &FirstFit),
+            ),
+            (
+                "00992111777.44.333....5555.6666.....8888..",
+                [2858, 10, 0, 5],
+                compaction(input, &BestFit),
+            ),
+            (
+                "00992111777.44.333....5555.6666.....8888..",
+                [2858, 10, 0, 5],
+                compaction(input, &WorstFit),
+            ),
+            (
+                "00992111777.44.33388885555.6666...........",
+                [2282, 10, 0, 3],
+                compaction(input, &UntilStable(FirstFit)),
+            ),
+            (
+                "00992111777.44.333666655558888............",
+                [2322, 10, 0, 2],
+                compaction(input, &UntilStable(WorstFit)),
+            ),
+            (
+                "0011123334455556666777888899..............",
+                [2453, 10, 0, 0],
+                compaction(input, &Defragment),
+            ),
+            // Best and worst fit choose different spans than first fit
+            ("0221........", [9, 3, 0, 0], compaction("13152", &FirstFit)),
+            (
+                "01...22.....",
+                [23, 3, 0, 1],
+                compaction("13152", &WorstFit),
+            ),
+            (
+                "0122........",
+                [11, 3, 0, 0],
+                compaction("13152", &UntilStable(WorstFit)),
+            ),
+            ("01.....22...", [31, 3, 0, 1], compaction("15132", &BestFit)),
+            (
+                "0122........",
+                [11, 3, 0, 0],
+                compaction("15132", &UntilStable(BestFit)),
+            ),
+        ] {
+            assert_eq!(compacted, (blocks(layout), metrics), "{layout}");
+        }
+
+        assert_eq!(compact_disk_checksum(input), 1928);
+        assert_eq!(whole_files_compact_disk_checksum(input), 2858);
+    }
+
+    #[test]
+    fn layout_strings_match_examples() {
+        let input = "2333133121414131402";
+
+        assert_eq!(
+            disk_layout_string(&compact_disk_layout(input)),
+            "0099811188827773336446555566.............."
+        );
+        assert_eq!(
+            disk_layout_string(&whole_files_compact_disk_layout(input)),
+            "00992111777.44.333....5555.6666.....8888.."
+        );
+        assert_eq!(
+            disk_layout_string(&disk_blocks("1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,1,2")),
+            "0,.,1,.,2,.,3,.,4,.,5,.,6,.,7,.,8,.,9,.,10,10"
+        );
+    }
+
+    #[test]
+    fn generated_maps_match_layout() {
+        let mut state = 7_u64;
+
+        for length in 1..200 {
+            let input = (0..length)
+                .map(|_| {
+                    state = state
+                        .wrapping_mul(6_364_136_223_846_793_005)
+                        .wrapping_add(1_442_695_040_888_963_407);
+
+                    (state >> 60).to_string()
+                })
+                .join(",");
+
+            assert_eq!(
+                whole_files_compact_disk_checksum(&input),
+                layout_checksum(&input),
+                "{input}"
+            );
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::VecDeque;
+use std::{collections::VecDeque, mem::replace};
 
 use grid::Grid;
 use itertools::Itertools;
@@ -85,6 +85,191 @@ pub fn fences_total_cost_sides(input: &str) -> usize {
     )
 }
 
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub enum Connectivity {
+    Orthogonal,
+    Diagonal,
+    Hexagonal,
+}
+
+impl Connectivity {
+    // Hexagonal grids use odd rows shifted right by half a plot
+    const ORTHOGONAL: [Pos; 4] = [
+        Pos::new(1, 0),
+        Pos::new(0, 1),
+        Pos::new(-1, 0),
+        Pos::new(0, -1),
+    ];
+    const DIAGONAL: [Pos; 8] = [
+        Pos::new(1, 0),
+        Pos::new(0, 1),
+        Pos::new(-1, 0),
+        Pos::new(0, -1),
+        Pos::new(1, 1),
+        Pos::new(-1, 1),
+        Pos::new(-1, -1),
+        Pos::new(1, -1),
+    ];
+    const HEXAGONAL_EVEN: [Pos; 6] = [
+        Pos::new(0, 1),
+        Pos::new(-1, 0),
+        Pos::new(-1, -1),
+        Pos::new(0, -1),
+        Pos::new(1, -1),
+        Pos::new(1, 0),
+    ];
+    const HEXAGONAL_ODD: [Pos; 6] = [
+        Pos::new(0, 1),
+        Pos::new(-1, 1),
+        Pos::new(-1, 0),
+        Pos::new(0, -1),
+        Pos::new(1, 0),
+        Pos::new(1, 1),
+    ];
+
+    fn neighbors(self, position: Pos) -> impl Iterator<Item = Pos> {
+        match self {
+            Self::Orthogonal => Self::ORTHOGONAL.as_slice(),
+            Self::Diagonal => Self::DIAGONAL.as_slice(),
+            Self::Hexagonal if position.x % 2 == 0 => Self::HEXAGONAL_EVEN.as_slice(),
+            Self::Hexagonal => Self::HEXAGONAL_ODD.as_slice(),
+        }
+        .iter()
+        .map(move |&offset| position + offset)
+    }
+
+    fn edges(self, position: Pos) -> impl Iterator<Item = Pos> {
+        // Square plots share edges only with orthogonal neighbors
+        match self {
+            Self::Orthogonal | Self::Diagonal => Self::Orthogonal,
+            Self::Hexagonal => Self::Hexagonal,
+        }
+        .neighbors(position)
+    }
+}
+
+#[allow(dead_code)]
+pub struct Region {
+    pub plant: u8,
+    pub cells: Vec<Pos>,
+    pub area: usize,
+    pub perimeter: usize,
+    pub sides: usize,
+    pub bounding_box: (Pos, Pos),
+    pub encloses_others: bool,
+}
+
+#[allow(dead_code)]
+pub fn garden_regions(input: &str, connectivity: Connectivity) -> Vec<Region> {
+    let farm = bytes_grid(input);
+    let (labels, regions_cells) = region_labels(&farm, connectivity);
+
+    // Measure every labeled region
+    regions_cells
+        .into_iter()
+        .enumerate()
+        .map(|(label, cells)| {
+            let in_region = |position| labels.pos_get(position) == Some(&label);
+
+            let (perimeter, sides) = region_perimeter_sides(&cells, in_region, connectivity);
+
+            let bounding_box =
+                cells
+                    .iter()
+                    .fold((cells[0], cells[0]), |(min, max), &Pos { x, y }| {
+                        (
+                            Pos::new(min.x.min(x), min.y.min(y)),
+                            Pos::new(max.x.max(x), max.y.max(y)),
+                        )
+                    });
+
+            Region {
+                plant: *farm.pos_index(cells[0]),
+                area: cells.len(),
+                perimeter,
+                sides,
+                encloses_others: region_encloses_others(
+                    in_region,
+                    bounding_box,
+                    cells.len(),
+                    connectivity,
+                ),
+                bounding_box,
+                cells,
+            }
+        })
+        .collect()
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub enum RegionLabeling {
+    FloodFill,
+    UnionFind,
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+pub struct RegionMeasures {
+    pub plant: u8,
+    pub area: usize,
+    pub perimeter: usize,
+    pub sides: usize,
+}
+
+#[allow(dead_code)]
+pub fn garden_region_measures(
+    input: &str,
+    labeling: RegionLabeling,
+    connectivity: Connectivity,
+) -> Vec<RegionMeasures> {
+    let farm = bytes_grid(input);
+
+    match labeling {
+        RegionLabeling::FloodFill => {
+            let (labels, regions_cells) = region_labels(&farm, connectivity);
+
+            regions_cells
+                .into_iter()
+                .enumerate()
+                .map(|(label, cells)| {
+                    let (perimeter, sides) = region_perimeter_sides(
+                        &cells,
+                        |position| labels.pos_get(position) == Some(&label),
+                        connectivity,
+                    );
+
+                    RegionMeasures {
+                        plant: *farm.pos_index(cells[0]),
+                        area: cells.len(),
+                        perimeter,
+                        sides,
+                    }
+                })
+                .collect()
+        }
+        RegionLabeling::UnionFind => union_find_region_measures(&farm, connectivity),
+    }
+}
+
+#[allow(dead_code)]
+pub fn fences_total_costs(
+    input: &str,
+    labeling: RegionLabeling,
+    connectivity: Connectivity,
+) -> (usize, usize) {
+    // Perimeter and sides fences costs at once
+    garden_region_measures(input, labeling, connectivity)
+        .into_iter()
+        .fold((0, 0), |(perimeter_cost, sides_cost), region| {
+            (
+                perimeter_cost + region.area * region.perimeter,
+                sides_cost + region.area * region.sides,
+            )
+        })
+}
+
 // ------------------------------------------------------------------------------------------------
 // Functions
 
@@ -140,3 +325,402 @@ where
 
     fences_cost
 }
+
+fn region_labels(farm: &Grid<u8>, connectivity: Connectivity) -> (Grid<usize>, Vec<Vec<Pos>>) {
+    let mut labels = Grid::init(farm.rows(), farm.cols(), usize::MAX);
+    let mut regions_cells = Vec::new();
+
+    // Flood fill every unlabeled plot with a new label
+    for start in farm.indexed_iter().map(|(position, _)| Pos::from(position)) {
+        if *labels.pos_index(start) != usize::MAX {
+            continue;
+        }
+
+        let (label, plant) = (regions_cells.len(), *farm.pos_index(start));
+        let mut cells = vec![start];
+        *labels.pos_index_mut(start) = label;
+        let mut next_cell = 0;
+
+        while let Some(&position) = cells.get(next_cell) {
+            next_cell += 1;
+
+            for neighbor in connectivity.neighbors(position) {
+                if farm.pos_get(neighbor) == Some(&plant)
+                    && *labels.pos_index(neighbor) == usize::MAX
+                {
+                    *labels.pos_index_mut(neighbor) = label;
+                    cells.push(neighbor);
+                }
+            }
+        }
+
+        regions_cells.push(cells);
+    }
+
+    (labels, regions_cells)
+}
+
+fn region_perimeter_sides<InRegion>(
+    cells: &[Pos],
+    in_region: InRegion,
+    connectivity: Connectivity,
+) -> (usize, usize)
+where
+    InRegion: Fn(Pos) -> bool + Copy,
+{
+    let perimeter = cells
+        .iter()
+        .flat_map(|&position| connectivity.edges(position))
+        .filter(|&neighbor| !in_region(neighbor))
+        .count();
+
+    // Consecutive hexagonal edges never line up, so every edge is a side
+    if matches!(connectivity, Connectivity::Hexagonal) {
+        return (perimeter, perimeter);
+    }
+
+    // Sides count equals corners count
+    let sides = cells
+        .iter()
+        .flat_map(|&position| {
+            position
+                .adjacent()
+                .zip(position.adjacent().chain(position.adjacent()).skip(1))
+                .zip(position.corners())
+                .filter(
+                    move |&((n1, n2), corner)| match (in_region(n1), in_region(n2)) {
+                        (false, false) => true,
+                        (true, true) => !in_region(corner),
+                        _ => false,
+                    },
+                )
+        })
+        .count();
+
+    (perimeter, sides)
+}
+
+fn region_encloses_others<InRegion>(
+    in_region: InRegion,
+    (min, max): (Pos, Pos),
+    area: usize,
+    connectivity: Connectivity,
+) -> bool
+where
+    InRegion: Fn(Pos) -> bool,
+{
+    // Flood fill from outside the bounding box, any unreached cell is enclosed
+    let bounds = (
+        Pos::new(min.x - 1, min.y - 1),
+        Pos::new(max.x + 1, max.y + 1),
+    );
+    #[allow(clippy::cast_sign_loss)]
+    let mut outside = GridMask::new((
+        (bounds.1.x - bounds.0.x + 1) as usize,
+        (bounds.1.y - bounds.0.y + 1) as usize,
+    ));
+    let offset = |position: Pos| Pos::new(position.x - bounds.0.x, position.y - bounds.0.y);
+
+    let mut stack = vec![bounds.0];
+    outside.set_true(offset(bounds.0));
+    let mut outside_count = 1;
+
+    while let Some(position) = stack.pop() {
+        for neighbor in connectivity.edges(position) {
+            if neighbor.in_bounds(bounds)
+                && !in_region(neighbor)
+                && outside.set_true(offset(neighbor))
+            {
+                outside_count += 1;
+                stack.push(neighbor);
+            }
+        }
+    }
+
+    outside.mask.len() != outside_count + area
+}
+
+fn union_find_region_measures(farm: &Grid<u8>, connectivity: Connectivity) -> Vec<RegionMeasures> {
+    fn find(parents: &mut [usize], mut plot: usize) -> usize {
+        // Path halving
+        while parents[plot] != plot {
+            parents[plot] = parents[parents[plot]];
+            plot = parents[plot];
+        }
+
+        plot
+    }
+
+    let cols = farm.cols();
+    let mut parents = (0..farm.rows() * cols).collect_vec();
+    let mut measures = Vec::with_capacity(parents.len());
+
+    // Row scan, measuring every plot locally and merging with already scanned neighbors
+    for ((row, col), &plot) in farm.indexed_iter() {
+        let position = Pos::from((row, col));
+        let same_plant = |position| farm.pos_get(position) == Some(&plot);
+        let (perimeter, corners) = region_perimeter_sides(&[position], same_plant, connectivity);
+
+        measures.push(RegionMeasures {
+            plant: plot,
+            area: 1,
+            perimeter,
+            sides: corners,
+        });
+
+        let index = row * cols + col;
+
+        for neighbor in connectivity.neighbors(position) {
+            if (neighbor.x, neighbor.y) >= (position.x, position.y) || !same_plant(neighbor) {
+                continue;
+            }
+
+            let (neighbor_row, neighbor_col) = neighbor.into();
+            let neighbor_index = neighbor_row * cols + neighbor_col;
+            let (root, neighbor_root) = (
+                find(&mut parents, index),
+                find(&mut parents, neighbor_index),
+            );
+
+            if root == neighbor_root {
+                continue;
+            }
+
+            // Union by area, merging measures into the new root
+            let (big, small) = if measures[root].area >= measures[neighbor_root].area {
+                (root, neighbor_root)
+            } else {
+                (neighbor_root, root)
+            };
+
+            parents[small] = big;
+            measures[big].area += measures[small].area;
+            measures[big].perimeter += measures[small].perimeter;
+            measures[big].sides += measures[small].sides;
+        }
+    }
+
+    // Keep roots in first plot order
+    let mut seen_roots = vec![false; parents.len()];
+
+    (0..parents.len())
+        .filter_map(|index| {
+            let root = find(&mut parents, index);
+
+            (!replace(&mut seen_roots[root], true)).then(|| measures[root])
+        })
+        .collect()
+}
+
+// ------------------------------------------------------------------------------------------------
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use itertools::iproduct;
+
+    use super::*;
+    use crate::random_utils::bench::{XorShift, bench_inputs, best_of_runs};
+
+    type RegionSummary = (char, usize, usize, usize, [isize; 4], bool);
+
+    fn regions_summary(input: &str, connectivity: Connectivity) -> Vec<RegionSummary> {
+        garden_regions(input, connectivity)
+            .into_iter()
+            .map(|region| {
+                let (min, max) = region.bounding_box;
+
+                (
+                    char::from(region.plant),
+                    region.area,
+                    region.perimeter,
+                    region.sides,
+                    [min.x, min.y, max.x, max.y],
+                    region.encloses_others,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn garden_regions_match_examples() {
+        assert_eq!(
+            regions_summary(
+                "OOOOO\nOXOXO\nOOOOO\nOXOXO\nOOOOO",
+                Connectivity::Orthogonal
+            ),
+            [
+                ('O', 21, 36, 20, [0, 0, 4, 4], true),
+                ('X', 1, 4, 4, [1, 1, 1, 1], false),
+                ('X', 1, 4, 4, [1, 3, 1, 3], false),
+                ('X', 1, 4, 4, [3, 1, 3, 1], false),
+                ('X', 1, 4, 4, [3, 3, 3, 3], false),
+            ]
+        );
+        assert_eq!(
+            regions_summary("AAAA\nBBCD\nBBCC\nEEEC", Connectivity::Orthogonal),
+            [
+                ('A', 4, 10, 4, [0, 0, 0, 3], false),
+                ('B', 4, 8, 4, [1, 0, 2, 1], false),
+                ('C', 4, 10, 8, [1, 2, 3, 3], false),
+                ('D', 1, 4, 4, [1, 3, 1, 3], false),
+                ('E', 3, 8, 4, [3, 0, 3, 2], false),
+            ]
+        );
+    }
+
+    #[test]
+    fn diagonal_and_hexagonal_regions_are_connected() {
+        // Corner touching plots join diagonally, squares still have four edges
+        assert_eq!(
+            regions_summary("AB\nBA", Connectivity::Diagonal),
+            [
+                ('A', 2, 8, 8, [0, 0, 1, 1], false),
+                ('B', 2, 8, 8, [0, 0, 1, 1], false),
+            ]
+        );
+
+        // Odd rows shift right, so only the second row first plot touches the first row last one
+        assert_eq!(
+            regions_summary("AB\nBA", Connectivity::Hexagonal),
+            [
+                ('A', 1, 6, 6, [0, 0, 0, 0], false),
+                ('B', 2, 10, 10, [0, 0, 1, 1], false),
+                ('A', 1, 6, 6, [1, 1, 1, 1], false),
+            ]
+        );
+        assert_eq!(
+            regions_summary("AA\nAA", Connectivity::Hexagonal),
+            [('A', 4, 14, 14, [0, 0, 1, 1], false)]
+        );
+    }
+
+    #[test]
+    fn region_labelings_match_on_examples() {
+        for (input, puzzle_costs) in [
+            ("AAAA\nBBCD\nBBCC\nEEEC", (140, 80)),
+            ("OOOOO\nOXOXO\nOOOOO\nOXOXO\nOOOOO", (772, 436)),
+            (
+                "RRRRIICCFF\nRRRRIICCCF\nVVRRRCCFFF\nVVRCCCJFFF\nVVVVCJJCFE\n\
+                 VVIVCCJJEE\nVVIIICJJEE\nMIIIIIJJEE\nMIIISIJEEE\nMMMISSJEEE",
+                (1930, 1206),
+            ),
+            ("EEEEE\nEXXXX\nEEEEE\nEXXXX\nEEEEE", (692, 236)),
+            (
+                "AAAAAA\nAAABBA\nAAABBA\nABBAAA\nABBAAA\nAAAAAA",
+                (1184, 368),
+            ),
+        ] {
+            assert_eq!(
+                (
+                    fences_total_cost_perimeter(input),
+                    fences_total_cost_sides(input)
+                ),
+                puzzle_costs
+            );
+
+            for connectivity in [
+                Connectivity::Orthogonal,
+                Connectivity::Diagonal,
+                Connectivity::Hexagonal,
+            ] {
+                let [flood_fill, union_find] =
+                    [RegionLabeling::FloodFill, RegionLabeling::UnionFind].map(|labeling| {
+                        garden_region_measures(input, labeling, connectivity)
+                            .into_iter()
+                            .map(|region| {
+                                (region.plant, region.area, region.perimeter, region.sides)
+                            })
+                            .sorted_unstable()
+                            .collect_vec()
+                    });
+
+                assert_eq!(flood_fill, union_find, "{input}");
+            }
+
+            assert_eq!(
+                fences_total_costs(input, RegionLabeling::UnionFind, Connectivity::Orthogonal),
+                puzzle_costs
+            );
+        }
+    }
+
+    // Farm of jittered Voronoi regions, averaging 36 plots like the puzzle input
+    fn generated_farm(size: usize, rng: &mut XorShift) -> (String, String) {
+        let seeds_size = size.div_ceil(6);
+        let mut seeds = Vec::with_capacity(seeds_size * seeds_size);
+
+        for _ in 0..seeds_size * seeds_size {
+            seeds.push((
+                rng.below(6),
+                rng.below(6),
+                b'A' + u8::try_from(rng.below(26)).expect("Expected letter"),
+            ));
+        }
+
+        let farm = (0..size)
+            .map(|x| {
+                (0..size)
+                    .map(|y| {
+                        let (seed_x, seed_y) = (x / 6, y / 6);
+
+                        iproduct!(
+                            seed_x.saturating_sub(1)..=(seed_x + 1).min(seeds_size - 1),
+                            seed_y.saturating_sub(1)..=(seed_y + 1).min(seeds_size - 1)
+                        )
+                        .map(|(seed_x, seed_y)| {
+                            let (offset_x, offset_y, plant) = seeds[seed_x * seeds_size + seed_y];
+
+                            (
+                                (seed_x * 6 + offset_x).abs_diff(x).pow(2)
+                                    + (seed_y * 6 + offset_y).abs_diff(y).pow(2),
+                                plant,
+                            )
+                        })
+                        .min()
+                        .expect("Expected seeds")
+                        .1 as char
+                    })
+                    .collect::<String>()
+            })
+            .join("\n");
+
+        (format!("generated {size}x{size}"), farm)
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with --release --ignored --nocapture"]
+    fn region_labelings_bench() {
+        // Generated farms of puzzle size and larger
+        let inputs = bench_inputs("GardenGroups", |rng| {
+            vec![generated_farm(140, rng), generated_farm(1000, rng)]
+        });
+
+        for (name, input) in inputs {
+            println!("{name}");
+
+            best_of_runs("puzzle parts", || {
+                (
+                    fences_total_cost_perimeter(&input),
+                    fences_total_cost_sides(&input),
+                )
+            });
+
+            for (connectivity_name, connectivity) in [
+                ("orthogonal", Connectivity::Orthogonal),
+                ("diagonal", Connectivity::Diagonal),
+            ] {
+                for (labeling_name, labeling) in [
+                    ("flood fill", RegionLabeling::FloodFill),
+                    ("union find", RegionLabeling::UnionFind),
+                ] {
+                    best_of_runs(&format!("{connectivity_name} {labeling_name}"), || {
+                        fences_total_costs(&input, labeling, connectivity)
+                    });
+                }
+            }
+
+            println!();
+        }
+    }
+}
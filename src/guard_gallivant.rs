@@ -1,8 +1,13 @@
+use std::mem::replace;
+
 use grid::Grid;
-use rustc_hash::FxHashSet;
+use itertools::Itertools;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use rustc_hash::{FxHashMap, FxHashSet};
 
 use crate::random_utils::{
     FxHashWithCapacity, bytes_grid,
+    grid_mask::GridMask,
     pos::{Dir, GridPosGet, Pos},
 };
 
@@ -13,22 +18,210 @@ pub fn unique_guard_positions_count(input: &str) -> u16 {
     // Count unique guard positions
     guard_lab_pos_dirs(
         input,
-        |lab_map, position| {
-            // Setup only finds initial guard position
-            *position = lab_map
-                .indexed_iter()
-                .find(|&(_, &c)| c == b'^')
-                .expect("Expected guard position")
-                .0
-                .into();
-
-            None::<()>
-        },
+        // Setup has nothing to prepare
+        |_| None::<()>,
         |_, count, _, _| *count += 1,
     ) + 1
 }
 
+#[allow(dead_code)]
 pub fn possible_obstruction_loops_count(input: &str) -> u16 {
+    // Count possible obstruction loops
+    guard_lab_pos_dirs(
+        input,
+        obstacles_setup,
+        |obstacles, count, position, direction| {
+            if obstruction_loops(obstacles, position, direction) {
+                *count += 1;
+            }
+        },
+    )
+}
+
+pub fn possible_obstruction_loops_count_jump_table(input: &str) -> usize {
+    let lab_map = bytes_grid(input);
+    let jump_table = JumpTable::new(&lab_map);
+    let mut visited_obstacles = TurnMarks::new(&lab_map);
+
+    // Check candidates one at a time against the jump table
+    obstruction_candidates(&lab_map)
+        .into_iter()
+        .filter(|&(position, direction)| {
+            jump_table_obstruction_loops(&jump_table, &mut visited_obstacles, position, direction)
+        })
+        .count()
+}
+
+#[allow(dead_code)]
+pub fn possible_obstruction_loops_count_parallel(input: &str) -> usize {
+    let lab_map = bytes_grid(input);
+    let jump_table = JumpTable::new(&lab_map);
+
+    // Check candidates in parallel against the shared jump table
+    obstruction_candidates(&lab_map)
+        .par_iter()
+        .map_init(
+            || TurnMarks::new(&lab_map),
+            |visited_obstacles, &(position, direction)| {
+                jump_table_obstruction_loops(&jump_table, visited_obstacles, position, direction)
+            },
+        )
+        .filter(|&loops| loops)
+        .count()
+}
+
+#[allow(dead_code)]
+pub fn possible_obstruction_loops_positions(input: &str) -> Vec<Pos> {
+    let mut positions = Vec::with_capacity(input.len() / 8);
+
+    // Collect possible obstruction loops positions in path order
+    guard_lab_pos_dirs(
+        input,
+        obstacles_setup,
+        |obstacles, _, position, direction| {
+            if obstruction_loops(obstacles, position, direction) {
+                positions.push(position);
+            }
+        },
+    );
+
+    positions
+}
+
+#[allow(dead_code)]
+pub fn guard_visited_positions(input: &str) -> Vec<Pos> {
+    // Unique guard positions in visiting order
+    guard_path_trace(input)
+        .into_iter()
+        .map(|(position, _)| position)
+        .unique()
+        .collect()
+}
+
+pub fn guard_path_trace(input: &str) -> Vec<(Pos, Dir)> {
+    let lab_map = bytes_grid(input);
+
+    // Puzzle guard always leaves the lab
+    let walk = guard_walk(&lab_map, guard_start(&lab_map));
+
+    assert!(
+        walk.loop_length.is_none(),
+        "Expected guard to leave the lab"
+    );
+
+    walk.trace
+}
+
+pub struct GuardWalk {
+    pub trace: Vec<(Pos, Dir)>,
+    pub loop_length: Option<usize>,
+}
+
+pub fn guard_start(lab_map: &Grid<u8>) -> (Pos, Dir) {
+    // Any guard marker gives starting position and direction
+    lab_map
+        .indexed_iter()
+        .find_map(|(position, &c)| {
+            match c {
+                b'v' => Some(Dir::S),
+                b'>' => Some(Dir::E),
+                b'^' => Some(Dir::N),
+                b'<' => Some(Dir::W),
+                _ => None,
+            }
+            .map(|direction| (Pos::from(position), direction))
+        })
+        .expect("Expected guard position")
+}
+
+pub fn guard_walk(lab_map: &Grid<u8>, start: (Pos, Dir)) -> GuardWalk {
+    let mut trace = vec![start];
+    let mut seen = FxHashMap::with_capacity(lab_map.rows() * lab_map.cols() / 4);
+    let (mut position, mut direction) = start;
+
+    seen.insert(start, 0);
+
+    // Follow path turning right before obstacles, until leaving the lab or looping
+    loop {
+        let mut turns = 0;
+
+        while lab_map.pos_get(position.move_dir(direction)) == Some(&b'#') {
+            direction.rotate_cw_mut();
+            turns += 1;
+
+            // Guard surrounded by obstacles spins in place forever
+            if turns == 4 {
+                return GuardWalk {
+                    trace,
+                    loop_length: Some(1),
+                };
+            }
+        }
+
+        position.move_dir_mut(direction);
+
+        if lab_map.pos_get(position).is_none() {
+            return GuardWalk {
+                trace,
+                loop_length: None,
+            };
+        }
+
+        // Same position and direction means the rest of the walk repeats
+        if let Some(&step) = seen.get(&(position, direction)) {
+            return GuardWalk {
+                loop_length: Some(trace.len() - step),
+                trace,
+            };
+        }
+
+        seen.insert((position, direction), trace.len());
+        trace.push((position, direction));
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Functions
+
+type Obstacles = (
+    FxHashSet<(usize, usize, Dir)>,
+    Vec<Vec<usize>>,
+    Vec<Vec<usize>>,
+);
+
+fn obstacles_lines(lab_map: &Grid<u8>) -> (Vec<Vec<usize>>, Vec<Vec<usize>>) {
+    let (mut rows_obstacles, mut cols_obstacles) = (
+        vec![Vec::with_capacity(lab_map.cols() / 4); lab_map.rows()],
+        vec![Vec::with_capacity(lab_map.rows() / 4); lab_map.cols()],
+    );
+
+    // Store sorted obstacle positions for every row and column
+    for ((x, y), &c) in lab_map.indexed_iter() {
+        if c == b'#' {
+            rows_obstacles[x].push(y);
+            cols_obstacles[y].push(x);
+        }
+    }
+
+    (rows_obstacles, cols_obstacles)
+}
+
+fn obstacles_setup(lab_map: &Grid<u8>) -> Obstacles {
+    // Setup stores obstacle positions for faster loop detection
+    let (rows_obstacles, cols_obstacles) = obstacles_lines(lab_map);
+
+    (
+        FxHashSet::with_capacity(rows_obstacles.len() * cols_obstacles.len() / 32),
+        rows_obstacles,
+        cols_obstacles,
+    )
+}
+
+fn obstruction_loops(
+    (visited_obstacles, rows_obstacles, cols_obstacles): &mut Obstacles,
+    position: Pos,
+    direction: Dir,
+) -> bool {
     #[inline]
     fn binary_insert(vec: &mut Vec<usize>, item: usize) -> usize {
         match vec.binary_search(&item) {
@@ -39,120 +232,87 @@ pub fn possible_obstruction_loops_count(input: &str) -> u16 {
         }
     }
 
-    // Count possible obstruction loops
-    guard_lab_pos_dirs(
-        input,
-        |lab_map, position| {
-            // Setup stores obstacle positions for faster loop detection
-            let (mut rows_obstacles, mut cols_obstacles) = (
-                vec![Vec::with_capacity(lab_map.cols() / 4); lab_map.rows()],
-                vec![Vec::with_capacity(lab_map.rows() / 4); lab_map.cols()],
-            );
+    // Insert time travel obstruction
+    let (mut position, mut direction) = (position, direction);
+    let (obstruction_x, obstruction_y) = position.into();
 
-            for ((x, y), &c) in lab_map.indexed_iter() {
-                match c {
-                    b'#' => {
-                        rows_obstacles[x].push(y);
-                        cols_obstacles[y].push(x);
-                    }
-                    b'.' => {}
-                    b'^' => *position = Pos::from((x, y)),
-                    _ => unreachable!("Invalid lab tile"),
-                }
-            }
+    position.move_dir_mut(direction.opposite());
 
-            (
-                FxHashSet::with_capacity(rows_obstacles.len() * cols_obstacles.len() / 32),
-                rows_obstacles,
-                cols_obstacles,
-            )
-        },
-        |(visited_obstacles, rows_obstacles, cols_obstacles), count, position, direction| {
-            // Insert time travel obstruction
-            let (mut position, mut direction) = (position, direction);
-            let (obstruction_x, obstruction_y) = position.into();
+    let (mut x, mut y) = position.into();
+    let obstruction_index = (
+        binary_insert(&mut rows_obstacles[obstruction_x], obstruction_y),
+        binary_insert(&mut cols_obstacles[obstruction_y], obstruction_x),
+    );
 
-            position.move_dir_mut(direction.opposite());
+    visited_obstacles.clear();
 
-            let (mut x, mut y) = position.into();
-            let obstruction_index = (
-                binary_insert(&mut rows_obstacles[obstruction_x], obstruction_y),
-                binary_insert(&mut cols_obstacles[obstruction_y], obstruction_x),
-            );
+    let loops = loop {
+        direction.rotate_cw_mut();
 
-            visited_obstacles.clear();
+        // Return if loop found
+        if !visited_obstacles.insert((x, y, direction)) {
+            break true;
+        }
 
-            loop {
-                direction.rotate_cw_mut();
+        // Follow path
+        if let Some(Some(next_obstacle)) = match direction {
+            Dir::S => cols_obstacles
+                .get(y)
+                .map(|vec| vec.iter().find(|&&obstacle| obstacle > x)),
+            Dir::E => rows_obstacles
+                .get(x)
+                .map(|vec| vec.iter().find(|&&obstacle| obstacle > y)),
+            Dir::N => cols_obstacles
+                .get(y)
+                .map(|vec| vec.iter().rev().find(|&&obstacle| obstacle < x)),
+            Dir::W => rows_obstacles
+                .get(x)
+                .map(|vec| vec.iter().rev().find(|&&obstacle| obstacle < y)),
+        } {
+            (x, y) = (
+                match direction {
+                    Dir::S => next_obstacle - 1,
+                    Dir::N => next_obstacle + 1,
+                    Dir::E | Dir::W => x,
+                },
+                match direction {
+                    Dir::S | Dir::N => y,
+                    Dir::E => next_obstacle - 1,
+                    Dir::W => next_obstacle + 1,
+                },
+            );
+        } else {
+            break false;
+        }
+    };
 
-                // Return if loop found
-                if !visited_obstacles.insert((x, y, direction)) {
-                    rows_obstacles[obstruction_x].remove(obstruction_index.0);
-                    cols_obstacles[obstruction_y].remove(obstruction_index.1);
-                    *count += 1;
-                    return;
-                }
+    // Remove time travel obstruction
+    rows_obstacles[obstruction_x].remove(obstruction_index.0);
+    cols_obstacles[obstruction_y].remove(obstruction_index.1);
 
-                // Follow path
-                if let Some(Some(next_obstacle)) = match direction {
-                    Dir::S => cols_obstacles
-                        .get(y)
-                        .map(|vec| vec.iter().find(|&&obstacle| obstacle > x)),
-                    Dir::E => rows_obstacles
-                        .get(x)
-                        .map(|vec| vec.iter().find(|&&obstacle| obstacle > y)),
-                    Dir::N => cols_obstacles
-                        .get(y)
-                        .map(|vec| vec.iter().rev().find(|&&obstacle| obstacle < x)),
-                    Dir::W => rows_obstacles
-                        .get(x)
-                        .map(|vec| vec.iter().rev().find(|&&obstacle| obstacle < y)),
-                } {
-                    (x, y) = (
-                        match direction {
-                            Dir::S => next_obstacle - 1,
-                            Dir::N => next_obstacle + 1,
-                            Dir::E | Dir::W => x,
-                        },
-                        match direction {
-                            Dir::S | Dir::N => y,
-                            Dir::E => next_obstacle - 1,
-                            Dir::W => next_obstacle + 1,
-                        },
-                    );
-                } else {
-                    rows_obstacles[obstruction_x].remove(obstruction_index.0);
-                    cols_obstacles[obstruction_y].remove(obstruction_index.1);
-                    return;
-                }
-            }
-        },
-    )
+    loops
 }
 
-// ------------------------------------------------------------------------------------------------
-// Functions
-
 fn guard_lab_pos_dirs<Setup, S, Count>(
     input: &str,
     setup_function: Setup,
-    counting_function: Count,
+    mut counting_function: Count,
 ) -> u16
 where
-    Setup: Fn(&mut Grid<u8>, &mut Pos) -> S,
-    Count: Fn(&mut S, &mut u16, Pos, Dir),
+    Setup: Fn(&Grid<u8>) -> S,
+    Count: FnMut(&mut S, &mut u16, Pos, Dir),
 {
-    // Parse lab map
+    // Parse lab map and guard start
     let mut lab_map = bytes_grid(input);
-    let (mut position, mut direction) = (Pos::new(0, 0), Dir::N);
+    let (mut position, mut direction) = guard_start(&lab_map);
     let mut count = 0;
 
     // Setup
-    let mut setup = setup_function(&mut lab_map, &mut position);
+    let mut setup = setup_function(&lab_map);
 
     // Follow path and store (position, direction) first unique tuple
     while lab_map.pos_get(position).is_some() {
-        if lab_map.pos_get(position.move_dir(direction)) == Some(&b'#') {
+        while lab_map.pos_get(position.move_dir(direction)) == Some(&b'#') {
             direction.rotate_cw_mut();
         }
 
@@ -167,3 +327,362 @@ where
 
     count
 }
+
+fn obstruction_candidates(lab_map: &Grid<u8>) -> Vec<(Pos, Dir)> {
+    let (mut position, mut direction) = guard_start(lab_map);
+    let mut visited = GridMask::new(lab_map.size());
+    let mut candidates = Vec::with_capacity(lab_map.rows() * lab_map.cols() / 2);
+
+    visited.set_true(position);
+
+    // Obstructions go on every position first visited by the guard, stopping if it never leaves
+    for _ in 0..4 * lab_map.rows() * lab_map.cols() {
+        let mut turns = 0;
+
+        while lab_map.pos_get(position.move_dir(direction)) == Some(&b'#') {
+            direction.rotate_cw_mut();
+            turns += 1;
+
+            if turns == 4 {
+                break;
+            }
+        }
+
+        position.move_dir_mut(direction);
+
+        if lab_map.pos_get(position).is_none() {
+            break;
+        }
+
+        if visited.set_true(position) {
+            candidates.push((position, direction));
+        }
+    }
+
+    candidates
+}
+
+struct TurnMarks {
+    cols: usize,
+    marks: Vec<u32>,
+    generation: u32,
+}
+
+impl TurnMarks {
+    fn new(lab_map: &Grid<u8>) -> Self {
+        Self {
+            cols: lab_map.cols(),
+            marks: vec![0; lab_map.rows() * lab_map.cols() * 4],
+            generation: 0,
+        }
+    }
+
+    // Forget all marks at once by moving to a new generation
+    const fn clear(&mut self) {
+        self.generation += 1;
+    }
+
+    fn insert(&mut self, (x, y): (usize, usize), direction: Dir) -> bool {
+        let mark = &mut self.marks[(x * self.cols + y) * 4 + usize::from(direction)];
+
+        replace(mark, self.generation) != self.generation
+    }
+}
+
+struct JumpTable {
+    cols: usize,
+    stops: Vec<[Option<usize>; 4]>,
+}
+
+impl JumpTable {
+    fn new(lab_map: &Grid<u8>) -> Self {
+        let (rows, cols) = lab_map.size();
+        let mut stops = vec![[None; 4]; rows * cols];
+
+        // Sweep lines both ways storing where the guard stops before the next obstacle
+        for x in 0..rows {
+            let (mut west_stop, mut east_stop) = (None, None);
+
+            for (y, reverse_y) in (0..cols).zip((0..cols).rev()) {
+                if lab_map[(x, y)] == b'#' {
+                    west_stop = Some(y + 1);
+                } else {
+                    stops[x * cols + y][usize::from(Dir::W)] = west_stop;
+                }
+
+                if lab_map[(x, reverse_y)] == b'#' {
+                    east_stop = reverse_y.checked_sub(1);
+                } else {
+                    stops[x * cols + reverse_y][usize::from(Dir::E)] = east_stop;
+                }
+            }
+        }
+
+        for y in 0..cols {
+            let (mut north_stop, mut south_stop) = (None, None);
+
+            for (x, reverse_x) in (0..rows).zip((0..rows).rev()) {
+                if lab_map[(x, y)] == b'#' {
+                    north_stop = Some(x + 1);
+                } else {
+                    stops[x * cols + y][usize::from(Dir::N)] = north_stop;
+                }
+
+                if lab_map[(reverse_x, y)] == b'#' {
+                    south_stop = reverse_x.checked_sub(1);
+                } else {
+                    stops[reverse_x * cols + y][usize::from(Dir::S)] = south_stop;
+                }
+            }
+        }
+
+        Self { cols, stops }
+    }
+
+    // Stop coordinate along the direction, patched locally with the time travel obstruction
+    fn stop(
+        &self,
+        (x, y): (usize, usize),
+        direction: Dir,
+        (obstruction_x, obstruction_y): (usize, usize),
+    ) -> Option<usize> {
+        let stop = self.stops[x * self.cols + y][usize::from(direction)];
+
+        match direction {
+            Dir::S if y == obstruction_y && x < obstruction_x => {
+                stop.map_or(obstruction_x - 1, |stop| stop.min(obstruction_x - 1))
+            }
+            Dir::E if x == obstruction_x && y < obstruction_y => {
+                stop.map_or(obstruction_y - 1, |stop| stop.min(obstruction_y - 1))
+            }
+            Dir::N if y == obstruction_y && x > obstruction_x => {
+                stop.map_or(obstruction_x + 1, |stop| stop.max(obstruction_x + 1))
+            }
+            Dir::W if x == obstruction_x && y > obstruction_y => {
+                stop.map_or(obstruction_y + 1, |stop| stop.max(obstruction_y + 1))
+            }
+            _ => return stop,
+        }
+        .into()
+    }
+}
+
+fn jump_table_obstruction_loops(
+    jump_table: &JumpTable,
+    visited_obstacles: &mut TurnMarks,
+    obstruction: Pos,
+    direction: Dir,
+) -> bool {
+    // Time travel obstruction is only patched in, never inserted
+    let obstruction_position = obstruction.into();
+    let (mut x, mut y) = obstruction.move_dir(direction.opposite()).into();
+    let mut direction = direction;
+
+    visited_obstacles.clear();
+
+    loop {
+        direction.rotate_cw_mut();
+
+        // Return if loop found
+        if !visited_obstacles.insert((x, y), direction) {
+            return true;
+        }
+
+        // Jump to next stop in constant time
+        let Some(stop) = jump_table.stop((x, y), direction, obstruction_position) else {
+            return false;
+        };
+
+        match direction {
+            Dir::S | Dir::N => x = stop,
+            Dir::E | Dir::W => y = stop,
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------------
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random_utils::bench::{XorShift, bench_inputs, best_of_runs, grid_string};
+
+    const EXAMPLE: &str = "\
+....#.....
+.........#
+..........
+..#.......
+.......#..
+..........
+.#..^.....
+........#.
+#.........
+......#...
+";
+
+    #[test]
+    fn guard_path_matches_example() {
+        let trace = guard_path_trace(EXAMPLE);
+
+        assert_eq!(trace[0], (Pos::new(6, 4), Dir::N));
+        assert_eq!(
+            trace.last().map(|&(position, _)| position),
+            Some(Pos::new(9, 7))
+        );
+        assert_eq!(guard_visited_positions(EXAMPLE).len(), 41);
+        assert_eq!(unique_guard_positions_count(EXAMPLE), 41);
+        assert_eq!(
+            possible_obstruction_loops_positions(EXAMPLE),
+            [(6, 3), (7, 6), (8, 3), (8, 1), (7, 7), (9, 7)].map(|(x, y)| Pos::new(x, y))
+        );
+    }
+
+    #[test]
+    fn guard_walk_detects_loops_and_markers() {
+        let lab_map = bytes_grid(".#...\n....#\n.^...\n#....\n...#.\n");
+        let walk = guard_walk(&lab_map, guard_start(&lab_map));
+
+        assert_eq!(walk.loop_length, Some(8));
+        assert_eq!(walk.trace.len(), 8);
+
+        let lab_map = bytes_grid(".#.\n#^#\n.#.\n");
+        let walk = guard_walk(&lab_map, guard_start(&lab_map));
+
+        assert_eq!(walk.loop_length, Some(1));
+        assert_eq!(walk.trace, [(Pos::new(1, 1), Dir::N)]);
+
+        for (marker, direction) in [('v', Dir::S), ('>', Dir::E), ('^', Dir::N), ('<', Dir::W)] {
+            let lab_map = bytes_grid(&format!("...\n.{marker}.\n...\n"));
+            let walk = guard_walk(&lab_map, guard_start(&lab_map));
+
+            assert_eq!(walk.loop_length, None);
+            assert_eq!(
+                walk.trace,
+                [
+                    (Pos::new(1, 1), direction),
+                    (Pos::new(1, 1).move_dir(direction), direction)
+                ]
+            );
+        }
+    }
+
+    // Obstructions on any free position that make the guard loop
+    fn brute_force_obstruction_loops_count(input: &str) -> usize {
+        let mut lab_map = bytes_grid(input);
+        let start = guard_start(&lab_map);
+
+        lab_map
+            .indexed_iter()
+            .filter(|&(_, &c)| c == b'.')
+            .map(|(position, _)| position)
+            .collect_vec()
+            .into_iter()
+            .filter(|&position| {
+                lab_map[position] = b'#';
+                let loops = guard_walk(&lab_map, start).loop_length.is_some();
+                lab_map[position] = b'.';
+
+                loops
+            })
+            .count()
+    }
+
+    #[test]
+    fn obstruction_loops_counters_match_any_guard_marker() {
+        // Rotated markers and a start turning twice in place
+        let inputs = [b'^', b'>', b'v', b'<']
+            .map(|marker| EXAMPLE.replace('^', &char::from(marker).to_string()))
+            .into_iter()
+            .chain([concat!(
+                "#.......\n",
+                "......#.\n",
+                "......#.\n",
+                ".#...#..\n",
+                "...#....\n",
+                "...^..#.\n",
+                "....##..\n",
+                "...#....\n",
+            )
+            .to_owned()]);
+
+        for input in inputs {
+            let count = brute_force_obstruction_loops_count(&input);
+
+            assert_eq!(usize::from(possible_obstruction_loops_count(&input)), count);
+            assert_eq!(possible_obstruction_loops_count_jump_table(&input), count);
+            assert_eq!(possible_obstruction_loops_count_parallel(&input), count);
+            assert_eq!(possible_obstruction_loops_positions(&input).len(), count);
+        }
+
+        assert_eq!(brute_force_obstruction_loops_count(EXAMPLE), 6);
+    }
+
+    // Labs with a long spiral walk
+    fn generated_labs(rng: &mut XorShift) -> Vec<(String, String)> {
+        (0..4)
+            .map(|lab| {
+                let mut lab_map = Grid::init(130, 130, b'.');
+                let (mut position, mut direction) = (Pos::new(65, 65), Dir::N);
+                let mut path = GridMask::new(lab_map.size());
+                path.set_true(position);
+
+                // Outward spiral walk, with legs growing every two turns
+                'spiral: for leg in 0.. {
+                    for _ in 0..=leg / 2 * 2 {
+                        position.move_dir_mut(direction);
+
+                        if lab_map.pos_get(position).is_none() {
+                            break 'spiral;
+                        }
+
+                        path.set_true(position);
+                    }
+
+                    let obstacle = position.move_dir(direction);
+
+                    if lab_map.pos_get(obstacle).is_none() {
+                        break;
+                    }
+
+                    *lab_map.pos_index_mut(obstacle) = b'#';
+                    direction.rotate_cw_mut();
+                }
+
+                // Scatter obstacles away from the walk
+                for (index, cell) in lab_map.iter_mut().enumerate() {
+                    if rng.below(100) < 3 && !path.mask[index] {
+                        *cell = b'#';
+                    }
+                }
+
+                lab_map[(65, 65)] = b'^';
+
+                (format!("generated lab {lab}"), grid_string(&lab_map))
+            })
+            .collect()
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with --release --ignored --nocapture"]
+    fn obstruction_loops_counting_bench() {
+        for (name, input) in bench_inputs("GuardGallivant", generated_labs) {
+            println!("{name}");
+
+            let counts = [
+                best_of_runs("sequential walk", || {
+                    usize::from(possible_obstruction_loops_count(&input))
+                }),
+                best_of_runs("jump table", || {
+                    possible_obstruction_loops_count_jump_table(&input)
+                }),
+                best_of_runs("parallel jump table", || {
+                    possible_obstruction_loops_count_parallel(&input)
+                }),
+            ];
+
+            assert!(counts.iter().all_equal());
+            println!();
+        }
+    }
+}
@@ -1,9 +1,12 @@
-use std::convert::identity;
-
-use itertools::Itertools;
+use grid::Grid;
+use itertools::{Either, Itertools};
 use rustc_hash::FxHashSet;
 
-use crate::random_utils::{FxHashWithCapacity, bytes_grid, pos::Pos};
+use crate::random_utils::{
+    FxHashWithCapacity, bytes_grid,
+    grid_mask::GridMask,
+    pos::{GridPosGet, Pos},
+};
 
 // ------------------------------------------------------------------------------------------------
 // Exports
@@ -21,15 +24,137 @@ pub fn trailheads_total_score(input: &str) -> usize {
 }
 
 pub fn trailheads_total_rating(input: &str) -> usize {
-    // Find trailheads total rating based on total unique paths to some peaks
-    trailheads_total(
-        input,
-        || 0,
-        |visited_peaks, _| *visited_peaks += 1,
-        identity,
+    // Find trailheads total rating based on total unique paths to some peaks, summed from peaks down
+    trailheads_total_rating_rules(input, TrailRules::default()).expect("Expected acyclic rules")
+}
+
+#[derive(Clone, Copy)]
+pub struct TrailRules {
+    pub start_height: u8,
+    pub end_height: u8,
+    pub min_step: i8,
+    pub max_step: i8,
+    pub diagonal: bool,
+}
+
+impl Default for TrailRules {
+    fn default() -> Self {
+        // Puzzle rules
+        Self {
+            start_height: 0,
+            end_height: 9,
+            min_step: 1,
+            max_step: 1,
+            diagonal: false,
+        }
+    }
+}
+
+impl TrailRules {
+    const fn is_acyclic(self) -> bool {
+        // Trails can only loop when allowed steps go both up and down, or stay at the same height
+        self.min_step > 0 || self.max_step < 0
+    }
+}
+
+#[allow(dead_code)]
+pub fn trailheads_total_score_rules(input: &str, rules: TrailRules) -> usize {
+    let topographic_map = bytes_grid(input);
+
+    // Flood from every trailhead, counting unique ends reached
+    trailheads(&topographic_map, rules)
+        .map(|trailhead| {
+            let mut visited = GridMask::new(topographic_map.size());
+            visited.set_true(trailhead);
+            let mut positions = vec![trailhead];
+            let mut ends_count = 0;
+
+            while let Some(position) = positions.pop() {
+                if map_height(&topographic_map, position) == Some(rules.end_height) {
+                    ends_count += 1;
+                    continue;
+                }
+
+                positions.extend(
+                    trail_steps(&topographic_map, position, rules)
+                        .filter(|&next| visited.set_true(next)),
+                );
+            }
+
+            ends_count
+        })
+        .sum()
+}
+
+pub fn trailheads_total_rating_rules(input: &str, rules: TrailRules) -> Option<usize> {
+    // Sum ratings of all trailheads at once, None when trails can loop
+    let topographic_map = bytes_grid(input);
+    let ratings = heights_ratings(&topographic_map, rules)?;
+
+    Some(
+        trailheads(&topographic_map, rules)
+            .map(|trailhead| *ratings.pos_index(trailhead))
+            .sum(),
     )
 }
 
+#[allow(dead_code)]
+pub fn trails_ratings(input: &str, rules: TrailRules) -> Option<Grid<usize>> {
+    heights_ratings(&bytes_grid(input), rules)
+}
+
+#[allow(dead_code)]
+pub fn trailhead_trails(
+    input: &str,
+    trailhead: Pos,
+    rules: TrailRules,
+    limit: Option<usize>,
+) -> Option<Vec<Vec<Pos>>> {
+    let topographic_map = bytes_grid(input);
+    let ratings = heights_ratings(&topographic_map, rules)?;
+    let limit = limit.unwrap_or(usize::MAX);
+
+    // Only trailheads in bounds and at start height have trails
+    if map_height(&topographic_map, trailhead) != Some(rules.start_height) {
+        return Some(Vec::new());
+    }
+
+    let mut trails = Vec::with_capacity(
+        ratings
+            .pos_get(trailhead)
+            .map_or(0, |&rating| rating.min(limit)),
+    );
+    let mut trail = vec![trailhead];
+    let mut next_steps = vec![(trailhead, 0)];
+
+    // Depth first enumeration, never stepping where no end is reachable
+    while let Some((position, depth)) = next_steps.pop() {
+        if trails.len() == limit {
+            break;
+        }
+
+        trail.truncate(depth);
+        trail.push(position);
+
+        if ratings.pos_get(position).is_none_or(|&rating| rating == 0) {
+            continue;
+        }
+
+        if map_height(&topographic_map, position) == Some(rules.end_height) {
+            trails.push(trail.clone());
+            continue;
+        }
+
+        next_steps.extend(
+            trail_steps(&topographic_map, position, rules)
+                .filter(|&next| *ratings.pos_index(next) > 0)
+                .map(|next| (next, depth + 1)),
+        );
+    }
+
+    Some(trails)
+}
+
 // ------------------------------------------------------------------------------------------------
 // Functions
 
@@ -45,34 +170,21 @@ where
     Total: Fn(I) -> usize,
 {
     let topographic_map = bytes_grid(input);
+    let rules = TrailRules::default();
 
     // Start from level 0 and BFS keeping track of peaks reached
-    topographic_map
-        .indexed_iter()
-        .filter(|&(_, &v)| v == b'0')
-        .map(|(position, _)| {
+    trailheads(&topographic_map, rules)
+        .map(|trailhead| {
             let mut positions: Vec<Pos> = Vec::with_capacity(24);
-            positions.push(position.into());
+            positions.push(trailhead);
             let mut visited_peaks = init();
 
             while !positions.is_empty() {
-                let map_ref = &topographic_map;
-
                 positions = positions
                     .into_iter()
-                    .flat_map(|pos| {
-                        pos.adjacent().filter(move |&next| {
-                            map_ref.get(next.x, next.y)
-                                == Some(
-                                    &(map_ref
-                                        .get(pos.x, pos.y)
-                                        .expect("Expected position inside map")
-                                        + 1),
-                                )
-                        })
-                    })
+                    .flat_map(|pos| trail_steps(&topographic_map, pos, rules))
                     .filter(|&pos| {
-                        if topographic_map.get(pos.x, pos.y) == Some(&b'9') {
+                        if map_height(&topographic_map, pos) == Some(rules.end_height) {
                             peaks_function(&mut visited_peaks, pos);
                             false
                         } else {
@@ -86,3 +198,232 @@ where
         })
         .sum()
 }
+
+fn trailheads(topographic_map: &Grid<u8>, rules: TrailRules) -> impl Iterator<Item = Pos> {
+    topographic_map
+        .indexed_iter()
+        .filter(move |&(_, &height)| height == b'0' + rules.start_height)
+        .map(|(position, _)| position.into())
+}
+
+fn map_height(topographic_map: &Grid<u8>, position: Pos) -> Option<u8> {
+    topographic_map
+        .pos_get(position)
+        .filter(|height| height.is_ascii_digit())
+        .map(|height| height - b'0')
+}
+
+fn trail_steps(
+    topographic_map: &Grid<u8>,
+    position: Pos,
+    rules: TrailRules,
+) -> impl Iterator<Item = Pos> {
+    let height = map_height(topographic_map, position);
+
+    // Move to any neighbor whose height difference is an allowed step
+    if rules.diagonal {
+        Either::Left(position.neighbors())
+    } else {
+        Either::Right(position.adjacent())
+    }
+    .filter(move |&next| {
+        height
+            .zip(map_height(topographic_map, next))
+            .is_some_and(|(height, next_height)| {
+                #[allow(clippy::cast_possible_wrap)]
+                let step = next_height as i8 - height as i8;
+
+                (rules.min_step..=rules.max_step).contains(&step)
+            })
+    })
+}
+
+fn heights_ratings(topographic_map: &Grid<u8>, rules: TrailRules) -> Option<Grid<usize>> {
+    if !rules.is_acyclic() {
+        return None;
+    }
+
+    let mut ratings = Grid::new(topographic_map.rows(), topographic_map.cols());
+
+    // Bucket positions by height, then sum ratings against steps direction from ends
+    let mut heights_positions = vec![Vec::new(); 10];
+
+    for (position, _) in topographic_map.indexed_iter() {
+        if let Some(height) = map_height(topographic_map, position.into()) {
+            heights_positions[usize::from(height)].push(Pos::from(position));
+        }
+    }
+
+    if rules.min_step > 0 {
+        heights_positions.reverse();
+    }
+
+    for position in heights_positions.into_iter().flatten() {
+        *ratings.pos_index_mut(position) =
+            if map_height(topographic_map, position) == Some(rules.end_height) {
+                1
+            } else {
+                trail_steps(topographic_map, position, rules)
+                    .map(|next| *ratings.pos_index(next))
+                    .sum()
+            };
+    }
+
+    Some(ratings)
+}
+
+// ------------------------------------------------------------------------------------------------
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "\
+89010123
+78121874
+87430965
+96549874
+45678903
+32019012
+01329801
+10456732
+";
+
+    #[test]
+    fn ratings_match_enumerated_trails() {
+        let rules = TrailRules::default();
+        let ratings = trails_ratings(EXAMPLE, rules).expect("Expected acyclic rules");
+        let topographic_map = bytes_grid(EXAMPLE);
+
+        assert_eq!(trailheads_total_score(EXAMPLE), 36);
+        assert_eq!(trailheads_total_rating(EXAMPLE), 81);
+
+        let trails_counts = trailheads(&topographic_map, rules)
+            .map(|trailhead| {
+                let trails = trailhead_trails(EXAMPLE, trailhead, rules, None)
+                    .expect("Expected acyclic rules");
+
+                assert_eq!(trails.len(), *ratings.pos_index(trailhead));
+
+                trails.len()
+            })
+            .collect_vec();
+
+        assert_eq!(trails_counts, [20, 24, 10, 4, 1, 4, 5, 8, 5]);
+        assert_eq!(trails_counts.iter().sum::<usize>(), 81);
+    }
+
+    #[test]
+    fn custom_trail_rules() {
+        let scores_ratings = |input, rules| {
+            (
+                trailheads_total_score_rules(input, rules),
+                trailheads_total_rating_rules(input, rules),
+            )
+        };
+
+        // Descending trails pair the same trailheads and peaks, through the same paths
+        let descending = TrailRules {
+            start_height: 9,
+            end_height: 0,
+            min_step: -1,
+            max_step: -1,
+            ..TrailRules::default()
+        };
+
+        assert_eq!(scores_ratings(EXAMPLE, descending), (36, Some(81)));
+
+        // Steps of two skip odd heights
+        let even_steps = TrailRules {
+            end_height: 4,
+            min_step: 2,
+            max_step: 2,
+            ..TrailRules::default()
+        };
+
+        assert_eq!(scores_ratings("02\n24", even_steps), (1, Some(2)));
+        assert_eq!(scores_ratings("01\n12", even_steps), (0, Some(0)));
+
+        let up_to_two_steps = TrailRules {
+            min_step: 1,
+            ..even_steps
+        };
+
+        assert_eq!(scores_ratings("012\n234", up_to_two_steps), (1, Some(3)));
+
+        // Diagonal moves reach corner neighbors
+        let diagonal = TrailRules {
+            end_height: 2,
+            diagonal: true,
+            ..TrailRules::default()
+        };
+
+        assert_eq!(scores_ratings("0..\n.1.\n..2", diagonal), (1, Some(1)));
+        assert_eq!(
+            scores_ratings(
+                "0..\n.1.\n..2",
+                TrailRules {
+                    diagonal: false,
+                    ..diagonal
+                }
+            ),
+            (0, Some(0))
+        );
+
+        // Flat or both ways steps can loop, so no ratings or trails
+        for (min_step, max_step) in [(-1, 1), (0, 1), (-1, 0)] {
+            let cyclic = TrailRules {
+                end_height: 3,
+                min_step,
+                max_step,
+                ..TrailRules::default()
+            };
+
+            assert_eq!(trailheads_total_rating_rules("0123", cyclic), None);
+            assert!(trails_ratings("0123", cyclic).is_none());
+            assert_eq!(trailhead_trails("0123", Pos::new(0, 0), cyclic, None), None);
+        }
+
+        assert_eq!(
+            trailheads_total_score_rules(
+                "0123",
+                TrailRules {
+                    end_height: 3,
+                    min_step: -1,
+                    max_step: 1,
+                    ..TrailRules::default()
+                }
+            ),
+            1
+        );
+    }
+
+    #[test]
+    fn only_trailheads_have_trails() {
+        let input = "\
+0123
+1234
+8765
+9876
+";
+        let rules = TrailRules::default();
+
+        assert_eq!(
+            trailhead_trails(input, Pos::new(0, 0), rules, None).map(|trails| trails.len()),
+            Some(16)
+        );
+
+        for position in [
+            Pos::new(1, 0),
+            Pos::new(2, 1),
+            Pos::new(-1, 0),
+            Pos::new(0, 4),
+        ] {
+            assert_eq!(
+                trailhead_trails(input, position, rules, None),
+                Some(Vec::new())
+            );
+        }
+    }
+}
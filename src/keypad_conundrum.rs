@@ -1,4 +1,4 @@
-use std::iter::once;
+use std::{array::from_fn, iter::once};
 
 use itertools::Itertools;
 use rustc_hash::FxHashMap;
@@ -8,46 +8,135 @@ use crate::random_utils::{FxHashWithCapacity, parse_number, pos::Pos};
 // ------------------------------------------------------------------------------------------------
 // Exports
 
-pub fn codes_complexity_3_robots(input: &str) -> usize {
+pub fn codes_complexity_3_robots(input: &str) -> u128 {
     // Calculate door codes complexity 3 robots deep
-    codes_complexity::<3>(input)
+    codes_complexity(input, 3).expect("Expected complexity to fit u128")
 }
 
-pub fn codes_complexity_26_robots(input: &str) -> usize {
+pub fn codes_complexity_26_robots(input: &str) -> u128 {
     // Calculate door codes complexity 26 robots deep
-    codes_complexity::<26>(input)
+    codes_complexity(input, 26).expect("Expected complexity to fit u128")
 }
 
-// ------------------------------------------------------------------------------------------------
-// Functions
+pub fn codes_complexity(input: &str, robots_count: usize) -> Option<u128> {
+    // Door codes complexity calculation, None on overflow
+    input.lines().try_fold(0, |complexity: u128, code| {
+        door_code_presses_count(code, robots_count)?
+            .checked_mul(parse_number(&code[0..3]))?
+            .checked_add(complexity)
+    })
+}
+
+#[allow(dead_code)]
+pub fn door_code_presses(code: &str, robots_count: usize) -> Option<String> {
+    // Shortest presses sequence through the standard keypads chain
+    keypads_chain_presses(code, &door_keypad_layouts(robots_count))
+}
+
+pub fn door_code_presses_count(code: &str, robots_count: usize) -> Option<u128> {
+    // Shortest presses sequence length through the standard keypads chain, None on overflow
+    door_code_presses_counting(code, robots_count, PressesCounting::Checked)
+}
+
+#[allow(dead_code)]
+pub fn door_code_presses_count_modulo(
+    code: &str,
+    robots_count: usize,
+    modulus: u64,
+) -> Option<u128> {
+    // Shortest presses sequence length through the standard keypads chain, None on zero modulus
+    door_code_presses_counting(code, robots_count, PressesCounting::Modulo(modulus))
+}
+
+#[allow(dead_code)]
+pub fn door_code_replay(presses: &str, robots_count: usize) -> Option<String> {
+    // Replay presses through the standard keypads chain
+    keypads_chain_replay(presses, &door_keypad_layouts(robots_count))
+}
+
+#[allow(dead_code)]
+pub fn keypads_chain_presses_count(code: &str, keypad_layouts: &[&str]) -> Option<usize> {
+    // Door keypad move costs already account for the whole chain, None without gap-free moves
+    sequence_cost(
+        code,
+        &keypads_chain_move_costs(
+            &keypad_layouts
+                .iter()
+                .map(|layout| Keypad::new(layout))
+                .collect_vec(),
+        )[0],
+    )
+}
+
+#[allow(dead_code)]
+pub fn keypads_chain_presses(code: &str, keypad_layouts: &[&str]) -> Option<String> {
+    let keypads = keypad_layouts
+        .iter()
+        .map(|layout| Keypad::new(layout))
+        .collect_vec();
+    let move_costs = keypads_chain_move_costs(&keypads);
+
+    // Expand sequence one keypad at a time, choosing moves cheapest for the controlling keypad
+    keypads.iter().zip(&move_costs[1..]).try_fold(
+        code.to_string(),
+        |sequence, (keypad, controlling_move_costs)| {
+            once('A')
+                .chain(sequence.chars())
+                .tuple_windows()
+                .map(|(start, end)| {
+                    keypad
+                        .moves(start, end)
+                        .into_iter()
+                        .filter_map(|movement| {
+                            Some((sequence_cost(&movement, controlling_move_costs)?, movement))
+                        })
+                        .min_by_key(|&(cost, _)| cost)
+                        .map(|(_, movement)| movement)
+                })
+                .collect()
+        },
+    )
+}
+
+#[allow(dead_code)]
+pub fn keypads_chain_replay(presses: &str, keypad_layouts: &[&str]) -> Option<String> {
+    // Each robot arm starts on the A key and types what the keypad below it presses
+    keypad_layouts
+        .iter()
+        .rev()
+        .skip(1)
+        .try_fold(presses.to_string(), |sequence, layout| {
+            let keypad = Keypad::new(layout);
+            let mut position = keypad.keys_positions[&'A'];
+            let mut typed = String::with_capacity(sequence.len() / 2);
+
+            for key in sequence.chars() {
+                match key {
+                    '^' => position.x -= 1,
+                    'v' => position.x += 1,
+                    '<' => position.y -= 1,
+                    '>' => position.y += 1,
+                    'A' => {}
+                    _ => return None,
+                }
+
+                // Robots panic when pointing at gaps or outside the keypad
+                let pointed_key = keypad.position_key(position)?;
 
-#[allow(clippy::too_many_lines)]
-fn codes_complexity<const ROBOTS_COUNT: usize>(input: &str) -> usize {
-    // Normal direction priority
-    const DIR_PRIORITIES: &str = "<v^>";
-    // Direction priority when connecting between first column and last row
-    const GAP_DIR_PRIORITIES: &str = "^>v<";
-
-    fn key_position(key: char) -> Pos {
-        Pos::from(match key {
-            '1' => (2, 0_usize),
-            '2' => (2, 1),
-            '3' => (2, 2),
-            '4' => (1, 0),
-            '5' => (1, 1),
-            '6' => (1, 2),
-            '7' => (0, 0),
-            '8' => (0, 1),
-            '9' => (0, 2),
-            '0' => (3, 1),
-            'A' => (3, 2),
-            _ => unreachable!("Invalid number"),
+                if key == 'A' {
+                    typed.push(pointed_key);
+                }
+            }
+
+            Some(typed)
         })
-    }
+}
 
+#[allow(dead_code)]
+pub fn best_directional_paths_match_derived(robots_count: usize) -> bool {
     // Best paths between any two directions (ignoring inefficient left-right/up-down movements)
-    fn best_directional_path<'a>(start: char, end: char) -> &'a str {
-        match (start, end) {
+    const fn best_directional_path<'a>(start: char, end: char) -> Option<&'a str> {
+        Some(match (start, end) {
             ('v', 'v') | ('>', '>') | ('^', '^') | ('<', '<') | ('A', 'A') => "A",
             ('v', '>') | ('^', 'A') | ('<', 'v') => ">A",
             ('v', '<') | ('>', 'v') | ('A', '^') => "<A",
@@ -61,84 +150,421 @@ fn codes_complexity<const ROBOTS_COUNT: usize>(input: &str) -> usize {
             ('A', 'v') => "<vA",
             ('A', '>') => "vA",
             ('A', '<') => "v<<A",
-            _ => unreachable!("Invalid start/end"),
+            _ => return None,
+        })
+    }
+
+    let keypad = Keypad::new(DIRECTIONAL_KEYPAD);
+    let mut move_costs = keypad.press_costs();
+
+    // Hand-written paths must cost as much as the derived best moves at every depth
+    (0..robots_count).all(|_| {
+        let derived_move_costs = keypad.move_costs(&move_costs);
+        let matching = derived_move_costs.iter().all(|(&(start, end), &cost)| {
+            best_directional_path(start, end)
+                .is_none_or(|path| sequence_cost(path, &move_costs) == Some(cost))
+        });
+
+        move_costs = derived_move_costs;
+        matching
+    })
+}
+
+// ------------------------------------------------------------------------------------------------
+// Functions
+
+const NUMERIC_KEYPAD: &str = "789\n456\n123\n 0A";
+const DIRECTIONAL_KEYPAD: &str = " ^A\n<v>";
+
+type MoveCosts = FxHashMap<(char, char), usize>;
+
+struct Keypad {
+    keys_positions: FxHashMap<char, Pos>,
+}
+
+impl Keypad {
+    fn new(layout: &str) -> Self {
+        let mut keys_positions = FxHashMap::with_capacity(16);
+
+        // Spaces are gaps the robot arm cannot point at
+        for (x, row) in layout.lines().enumerate() {
+            for (y, key) in row.chars().enumerate() {
+                if key != ' ' {
+                    keys_positions.insert(key, Pos::from((x, y)));
+                }
+            }
         }
+
+        Self { keys_positions }
+    }
+
+    fn keys(&self) -> impl Iterator<Item = char> + Clone + '_ {
+        self.keys_positions.keys().copied()
+    }
+
+    fn position_key(&self, position: Pos) -> Option<char> {
+        self.keys_positions
+            .iter()
+            .find(|&(_, &key_position)| key_position == position)
+            .map(|(&key, _)| key)
+    }
+
+    // Directly pressed keys cost nothing but the press itself
+    fn press_costs(&self) -> MoveCosts {
+        self.keys()
+            .cartesian_product(self.keys())
+            .map(|keys| (keys, 1))
+            .collect()
     }
 
-    // Recursive memoized path cost function
-    fn path_cost<'a>(
-        path: &'a str,
-        robots_left: usize,
-        cache: &mut FxHashMap<(&'a str, usize), usize>,
-    ) -> usize {
-        if let Some(&cost) = cache.get(&(path, robots_left)) {
-            cost
+    // Candidate moves between two keys, along every shortest path avoiding gaps
+    fn moves(&self, start: char, end: char) -> Vec<String> {
+        const STEPS: [(char, Pos); 4] = [
+            ('<', Pos::new(0, -1)),
+            ('>', Pos::new(0, 1)),
+            ('^', Pos::new(-1, 0)),
+            ('v', Pos::new(1, 0)),
+        ];
+
+        let (start_position, end_position) = (
+            self.keys_positions[&start],
+            *self.keys_positions.get(&end).expect("Key not found"),
+        );
+
+        // Distances to the end key, stepping on keys only
+        let mut distances = FxHashMap::with_capacity(self.keys_positions.len());
+        distances.insert(end_position, 0);
+        let mut positions = vec![end_position];
+        let mut next_position = 0;
+
+        while let Some(&position) = positions.get(next_position) {
+            next_position += 1;
+            let distance = distances[&position] + 1;
+
+            for (_, step) in STEPS {
+                let previous = position - step;
+
+                if self.position_key(previous).is_some() && !distances.contains_key(&previous) {
+                    distances.insert(previous, distance);
+                    positions.push(previous);
+                }
+            }
+        }
+
+        // Enumerate shortest paths, horizontal steps first
+        let mut movements = Vec::new();
+        let mut paths = if distances.contains_key(&start_position) {
+            vec![(start_position, String::new())]
         } else {
-            let cost = once('A')
-                .chain(path.chars())
+            Vec::new()
+        };
+
+        while let Some((position, movement)) = paths.pop() {
+            if position == end_position {
+                movements.push(movement + "A");
+                continue;
+            }
+
+            let distance = distances[&position];
+
+            for &(key, step) in STEPS.iter().rev() {
+                let next = position + step;
+
+                if distances.get(&next) == Some(&(distance - 1)) {
+                    paths.push((next, movement.clone() + &key.to_string()));
+                }
+            }
+        }
+
+        // Straighter moves come first
+        movements.sort_by_key(|movement| {
+            movement
+                .chars()
                 .tuple_windows()
-                .map(|(start, end)| {
-                    let path = if start == end {
-                        "A"
-                    } else {
-                        best_directional_path(start, end)
-                    };
-
-                    if robots_left == 1 {
-                        path.len()
-                    } else {
-                        path_cost(path, robots_left - 1, cache)
+                .filter(|(a, b)| a != b)
+                .count()
+        });
+
+        movements
+    }
+
+    // Cheapest moves costs on this keypad given the move costs of the keypad controlling it
+    fn move_costs(&self, controlling_move_costs: &MoveCosts) -> MoveCosts {
+        // Keys pairs without gap-free moves are left out
+        self.keys()
+            .cartesian_product(self.keys())
+            .filter_map(|(start, end)| {
+                Some((
+                    (start, end),
+                    self.moves(start, end)
+                        .iter()
+                        .filter_map(|movement| sequence_cost(movement, controlling_move_costs))
+                        .min()?,
+                ))
+            })
+            .collect()
+    }
+}
+
+fn door_keypad_layouts(robots_count: usize) -> Vec<&'static str> {
+    // Door keypad, then directional keypads for every robot but the door one and the human
+    once(NUMERIC_KEYPAD)
+        .chain([DIRECTIONAL_KEYPAD].repeat(robots_count))
+        .collect()
+}
+
+fn keypads_chain_move_costs(keypads: &[Keypad]) -> Vec<MoveCosts> {
+    // Door keypad comes first, human operated keypad comes last
+    let (human_keypad, robots_keypads) = keypads
+        .split_last()
+        .expect("Expected at least one keypad layout");
+
+    // Derive move costs one robot operated keypad at a time
+    let mut move_costs = vec![human_keypad.press_costs()];
+
+    for keypad in robots_keypads.iter().rev() {
+        move_costs.push(keypad.move_costs(move_costs.last().expect("Expected move costs")));
+    }
+
+    move_costs.reverse();
+    move_costs
+}
+
+fn sequence_cost(sequence: &str, move_costs: &MoveCosts) -> Option<usize> {
+    // Every sequence starts from the A key, None on impossible moves
+    once('A')
+        .chain(sequence.chars())
+        .tuple_windows()
+        .map(|keys| move_costs.get(&keys).copied())
+        .sum()
+}
+
+#[derive(Clone, Copy)]
+enum PressesCounting {
+    Checked,
+    Modulo(u64),
+}
+
+impl PressesCounting {
+    fn add(self, a: u128, b: u128) -> Option<u128> {
+        match self {
+            Self::Checked => a.checked_add(b),
+            Self::Modulo(modulus) => Some((a + b) % u128::from(modulus)),
+        }
+    }
+
+    fn mul(self, a: u128, b: u128) -> Option<u128> {
+        match self {
+            Self::Checked => a.checked_mul(b),
+            Self::Modulo(modulus) => Some(a * b % u128::from(modulus)),
+        }
+    }
+
+    fn dot(self, a: &[u128; 25], b: &[u128; 25]) -> Option<u128> {
+        a.iter()
+            .zip(b)
+            .try_fold(0, |dot, (&a, &b)| self.add(dot, self.mul(a, b)?))
+    }
+}
+
+fn door_code_presses_counting(
+    code: &str,
+    robots_count: usize,
+    counting: PressesCounting,
+) -> Option<u128> {
+    // Best moves stop changing after only a few robots
+    const STABLE_MOVES_DEPTH: usize = 16;
+    // Directional keys order for transitions indices
+    const DIRECTIONAL_KEYS: [char; 5] = ['A', '^', '<', 'v', '>'];
+
+    // Directional transitions indices of a move, starting from the A key
+    fn transitions(movement: &str) -> impl Iterator<Item = usize> + '_ {
+        once('A')
+            .chain(movement.chars())
+            .tuple_windows()
+            .map(|keys: (char, char)| {
+                let [start, end] = <[char; 2]>::from(keys).map(|key| {
+                    DIRECTIONAL_KEYS
+                        .iter()
+                        .position(|&directional_key| directional_key == key)
+                        .expect("Invalid directional key")
+                });
+
+                start * 5 + end
+            })
+    }
+
+    // Cheapest move between two keys, given exact directional transitions costs
+    fn cheapest_move(
+        keypad: &Keypad,
+        start: char,
+        end: char,
+        costs: &[u128; 25],
+    ) -> Option<String> {
+        keypad
+            .moves(start, end)
+            .into_iter()
+            .min_by_key(|movement| transitions(movement).map(|t| costs[t]).sum::<u128>())
+    }
+
+    if matches!(counting, PressesCounting::Modulo(0)) {
+        return None;
+    }
+
+    // Without robots the door keypad is pressed directly
+    if robots_count == 0 {
+        return counting.add(code.chars().count() as u128, 0);
+    }
+
+    let (numeric_keypad, directional_keypad) =
+        (Keypad::new(NUMERIC_KEYPAD), Keypad::new(DIRECTIONAL_KEYPAD));
+    let directional_depth = robots_count - 1;
+
+    // Exact directional transitions costs until best moves are stable
+    let mut exact_costs = [1; 25];
+
+    for _ in 0..directional_depth.min(STABLE_MOVES_DEPTH) {
+        let mut next_costs = [0; 25];
+
+        for (pair, next_cost) in next_costs.iter_mut().enumerate() {
+            let movement = cheapest_move(
+                &directional_keypad,
+                DIRECTIONAL_KEYS[pair / 5],
+                DIRECTIONAL_KEYS[pair % 5],
+                &exact_costs,
+            )?;
+
+            *next_cost = transitions(&movement).map(|t| exact_costs[t]).sum();
+        }
+
+        exact_costs = next_costs;
+    }
+
+    let mut costs = exact_costs.map(|cost| counting.add(cost, 0).expect("Expected small cost"));
+
+    // Apply stable best moves transitions matrix power to the remaining depth
+    if let Some(mut exponent) = directional_depth.checked_sub(STABLE_MOVES_DEPTH) {
+        let mut transitions_matrix = [[0; 25]; 25];
+
+        for (pair, row) in transitions_matrix.iter_mut().enumerate() {
+            for t in transitions(&cheapest_move(
+                &directional_keypad,
+                DIRECTIONAL_KEYS[pair / 5],
+                DIRECTIONAL_KEYS[pair % 5],
+                &exact_costs,
+            )?) {
+                row[t] += 1;
+            }
+        }
+
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                let previous_costs = costs;
+
+                for (cost, row) in costs.iter_mut().zip(&transitions_matrix) {
+                    *cost = counting.dot(row, &previous_costs)?;
+                }
+            }
+
+            exponent >>= 1;
+
+            if exponent > 0 {
+                let columns: [[u128; 25]; 25] = from_fn(|c| from_fn(|r| transitions_matrix[r][c]));
+                let mut squared_matrix = [[0; 25]; 25];
+
+                for (squared_row, row) in squared_matrix.iter_mut().zip(&transitions_matrix) {
+                    for (squared, column) in squared_row.iter_mut().zip(&columns) {
+                        *squared = counting.dot(row, column)?;
                     }
-                })
-                .sum();
+                }
 
-            cache.insert((path, robots_left), cost);
-            cost
+                transitions_matrix = squared_matrix;
+            }
         }
     }
 
-    // Door codes complexity calculation
-    input
-        .lines()
-        .map(|code| {
-            parse_number::<usize>(&code[0..3])
-                * path_cost(
-                    // Initial door code best path search
-                    &once('A')
-                        .chain(code.chars())
-                        .tuple_windows()
-                        .map(|(start, end)| {
-                            if start == end {
-                                return "A".to_string();
-                            }
-
-                            let priorities = if "0A".contains(start) && "147".contains(end)
-                                || "147".contains(start) && "0A".contains(end)
-                            {
-                                GAP_DIR_PRIORITIES
-                            } else {
-                                DIR_PRIORITIES
-                            };
-                            let dpos = key_position(end) - key_position(start);
-                            let mut movement = String::new();
-
-                            for key in priorities.chars() {
-                                #[allow(clippy::cast_sign_loss)]
-                                match key {
-                                    '<' if dpos.y < 0 => movement += &"<".repeat(-dpos.y as usize),
-                                    '>' if dpos.y > 0 => movement += &">".repeat(dpos.y as usize),
-                                    '^' if dpos.x < 0 => movement += &"^".repeat(-dpos.x as usize),
-                                    'v' if dpos.x > 0 => movement += &"v".repeat(dpos.x as usize),
-                                    _ => {}
-                                }
-                            }
-
-                            movement + "A"
-                        })
-                        .collect::<String>(),
-                    ROBOTS_COUNT - 1,
-                    &mut FxHashMap::with_capacity(ROBOTS_COUNT * ROBOTS_COUNT / 2),
-                )
-        })
-        .sum()
+    // Door code presses with numeric keypad best moves
+    once('A')
+        .chain(code.chars())
+        .tuple_windows()
+        .map(|(start, end)| cheapest_move(&numeric_keypad, start, end, &exact_costs))
+        .collect::<Option<Vec<_>>>()?
+        .iter()
+        .flat_map(|movement| transitions(movement))
+        .try_fold(0, |count, t| counting.add(count, costs[t]))
+}
+
+// ------------------------------------------------------------------------------------------------
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gap_crossing_moves_are_avoided() {
+        // Going from 1 to 2 needs a detour through the bottom row
+        let keypad_layouts = ["1 2\n34A", DIRECTIONAL_KEYPAD, DIRECTIONAL_KEYPAD];
+
+        for code in ["21A", "12A", "4321A"] {
+            let presses =
+                keypads_chain_presses(code, &keypad_layouts).expect("Expected gap-free moves");
+
+            assert_eq!(
+                keypads_chain_replay(&presses, &keypad_layouts).as_deref(),
+                Some(code)
+            );
+            assert_eq!(
+                keypads_chain_presses_count(code, &keypad_layouts),
+                Some(presses.len())
+            );
+        }
+    }
+
+    #[test]
+    fn both_corners_gaps_moves_are_found() {
+        let keypad_layouts = ["AB \nCDE\n FG", DIRECTIONAL_KEYPAD];
+
+        for code in ["GA", "EC", "BFA"] {
+            let presses =
+                keypads_chain_presses(code, &keypad_layouts).expect("Expected gap-free moves");
+
+            assert_eq!(
+                keypads_chain_replay(&presses, &keypad_layouts).as_deref(),
+                Some(code)
+            );
+        }
+    }
+
+    #[test]
+    fn unreachable_keys_have_no_presses() {
+        let keypad_layouts = ["A 1", DIRECTIONAL_KEYPAD];
+
+        assert_eq!(keypads_chain_presses("1", &keypad_layouts), None);
+        assert_eq!(keypads_chain_presses_count("1", &keypad_layouts), None);
+    }
+
+    #[test]
+    fn example_codes_complexity_matches_derived_costs() {
+        let codes = "029A\n980A\n179A\n456A\n379A";
+
+        assert_eq!(codes_complexity(codes, 3), Some(126_384));
+        assert_eq!(codes_complexity(codes, 26), Some(154_115_708_116_294));
+        assert!((1..=26).all(best_directional_paths_match_derived));
+    }
+
+    #[test]
+    fn zero_robots_and_zero_modulus_are_handled() {
+        for code in ["029A", "980A", "179A"] {
+            let presses_count = door_code_presses(code, 0).map(|presses| presses.len() as u128);
+
+            assert_eq!(door_code_presses_count(code, 0), presses_count);
+            assert_eq!(
+                door_code_presses_count_modulo(code, 0, 3),
+                presses_count.map(|count| count % 3)
+            );
+            assert_eq!(door_code_presses_count_modulo(code, 0, 0), None);
+            assert_eq!(door_code_presses_count_modulo(code, 25, 0), None);
+        }
+    }
 }
@@ -40,7 +40,7 @@ use code_chronicle::unique_key_lock_pairs_count;
 use crossed_wires::{final_z_wires_value, ripple_carry_adder_swapped_wires};
 use disk_fragmenter::{compact_disk_checksum, whole_files_compact_disk_checksum};
 use garden_groups::{fences_total_cost_perimeter, fences_total_cost_sides};
-use guard_gallivant::{possible_obstruction_loops_count, unique_guard_positions_count};
+use guard_gallivant::{possible_obstruction_loops_count_jump_table, unique_guard_positions_count};
 use historian_hysteria::{lists_similarity_score, lists_total_distance};
 use hoof_it::{trailheads_total_rating, trailheads_total_score};
 use keypad_conundrum::{codes_complexity_3_robots, codes_complexity_26_robots};
@@ -180,7 +180,7 @@ pub fn main() {
         6,
         "GuardGallivant",
         unique_guard_positions_count,
-        possible_obstruction_loops_count
+        possible_obstruction_loops_count_jump_table
     );
 
     pretty_solution_2!(
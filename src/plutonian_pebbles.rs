@@ -1,4 +1,7 @@
+use std::iter::from_fn;
+
 use itertools::Itertools;
+use num_modular::{ModularCoreOps, ModularPow, ModularUnaryOps};
 use rustc_hash::FxHashMap;
 
 use crate::random_utils::{FxHashWithCapacity, parse_numbers_whitespace};
@@ -6,45 +9,541 @@ use crate::random_utils::{FxHashWithCapacity, parse_numbers_whitespace};
 // ------------------------------------------------------------------------------------------------
 // Exports
 
-pub fn stones_expansion_25_blinks(input: &str) -> u64 {
-    stones_expansion::<25>(input)
+pub fn stones_expansion_25_blinks(input: &str) -> u128 {
+    stones_expansion(input, 25, StoneRules::default())
+        .expect("Expected stones to fit u64 and count to fit u128")
+}
+
+pub fn stones_expansion_75_blinks(input: &str) -> u128 {
+    stones_expansion(input, 75, StoneRules::default())
+        .expect("Expected stones to fit u64 and count to fit u128")
+}
+
+#[derive(Clone, Copy)]
+pub struct StoneRules {
+    pub zero_replacement: u64,
+    pub split_base: u64,
+    pub multiplier: u64,
+}
+
+impl Default for StoneRules {
+    fn default() -> Self {
+        // Puzzle rules
+        Self {
+            zero_replacement: 1,
+            split_base: 10,
+            multiplier: 2024,
+        }
+    }
+}
+
+impl StoneRules {
+    // Stones after blinking once, None on invalid split base or overflow
+    pub const fn blink(self, stone: u64) -> Option<(u64, Option<u64>)> {
+        if self.split_base < 2 {
+            return None;
+        }
+
+        if stone == 0 {
+            return Some((self.zero_replacement, None));
+        }
+
+        let digits_count = stone.ilog(self.split_base) + 1;
+
+        // Even digits count stones split in two halves, others get multiplied
+        if digits_count.is_multiple_of(2) {
+            let digits_pow = self.split_base.pow(digits_count / 2);
+
+            Some((stone / digits_pow, Some(stone % digits_pow)))
+        } else {
+            let Some(stone) = stone.checked_mul(self.multiplier) else {
+                return None;
+            };
+
+            Some((stone, None))
+        }
+    }
+}
+
+pub fn stones_expansion(input: &str, blinks: usize, rules: StoneRules) -> Option<u128> {
+    let mut stones = FxHashMap::with_capacity(blinks.min(128).pow(2));
+    parse_numbers_whitespace(input).for_each(|stone| *stones.entry(stone).or_default() += 1);
+
+    // Map stones to counts and blink many times, None on overflow
+    for _ in 0..blinks {
+        blink_stones(&mut stones, rules)?;
+    }
+
+    stones
+        .values()
+        .try_fold(0_u128, |total, &count| total.checked_add(count))
+}
+
+#[allow(dead_code)]
+pub struct BlinkState {
+    pub blink: usize,
+    pub stones_count: u128,
+    pub distinct_stones_count: usize,
+    pub largest_stone: u64,
+    pub initial_stones_counts: Vec<u128>,
+}
+
+#[allow(dead_code)]
+pub fn blink_states(input: &str, rules: StoneRules) -> impl Iterator<Item = BlinkState> {
+    // Stones of every initial stone are tracked separately too
+    let mut stones: FxHashMap<u64, u128> = FxHashMap::with_capacity(4096);
+    let mut initial_stones = parse_numbers_whitespace(input)
+        .map(|stone| FxHashMap::from_iter([(stone, 1)]))
+        .collect_vec();
+
+    for initial_stone in &initial_stones {
+        for (&stone, &count) in initial_stone {
+            *stones.entry(stone).or_default() += count;
+        }
+    }
+
+    let mut blink = 0;
+
+    // Yield current state then blink, ending for good on overflow
+    from_fn(move || {
+        if blink > 0 {
+            blink_stones(&mut stones, rules)?;
+
+            for initial_stone in &mut initial_stones {
+                blink_stones(initial_stone, rules)?;
+            }
+        }
+
+        blink += 1;
+
+        Some(BlinkState {
+            blink: blink - 1,
+            stones_count: stones
+                .values()
+                .try_fold(0_u128, |total, &count| total.checked_add(count))?,
+            distinct_stones_count: stones.len(),
+            largest_stone: stones.keys().max().copied().unwrap_or_default(),
+            initial_stones_counts: initial_stones
+                .iter()
+                .map(|initial_stone| {
+                    initial_stone
+                        .values()
+                        .try_fold(0_u128, |total, &count| total.checked_add(count))
+                })
+                .collect::<Option<_>>()?,
+        })
+    })
+    .fuse()
+}
+
+#[allow(dead_code)]
+pub struct StonesGraph {
+    pub values: Vec<u64>,
+    pub transitions: Vec<(usize, Option<usize>)>,
+    pub initial_counts: Vec<u64>,
+    pub closure_blinks: usize,
 }
 
-pub fn stones_expansion_75_blinks(input: &str) -> u64 {
-    stones_expansion::<75>(input)
+#[allow(dead_code)]
+pub fn stones_transition_graph(
+    input: &str,
+    rules: StoneRules,
+    max_values: usize,
+) -> Option<StonesGraph> {
+    let mut indices = FxHashMap::with_capacity(max_values.min(4096));
+    let (mut values, mut transitions) = (Vec::new(), Vec::new());
+    let mut initial_counts = Vec::new();
+
+    let mut stone_index = |stone, values: &mut Vec<u64>| {
+        *indices.entry(stone).or_insert_with(|| {
+            values.push(stone);
+            values.len() - 1
+        })
+    };
+
+    for stone in parse_numbers_whitespace(input) {
+        let index = stone_index(stone, &mut values);
+
+        initial_counts.resize(values.len(), 0);
+        initial_counts[index] += 1;
+    }
+
+    // Breadth first closure of stone values, one blink at a time
+    let (mut frontier_start, mut closure_blinks) = (0, 0_usize);
+
+    while frontier_start < values.len() {
+        let frontier_end = values.len();
+
+        for index in frontier_start..frontier_end {
+            let (left_stone, right_stone) = rules.blink(values[index])?;
+
+            transitions.push((
+                stone_index(left_stone, &mut values),
+                right_stone.map(|stone| stone_index(stone, &mut values)),
+            ));
+        }
+
+        if values.len() > max_values {
+            return None;
+        }
+
+        frontier_start = frontier_end;
+        closure_blinks += 1;
+    }
+
+    initial_counts.resize(values.len(), 0);
+
+    Some(StonesGraph {
+        values,
+        transitions,
+        initial_counts,
+        closure_blinks: closure_blinks.saturating_sub(1),
+    })
+}
+
+impl StonesGraph {
+    // Total stones count modulo a prime, fast-forwarding through the counts linear recurrence
+    pub fn stones_count_modulo(&self, blinks: u64, modulus: u64) -> Option<u64> {
+        // Recurrence search needs field inverses, so None for other moduli
+        if !is_prime(modulus) {
+            return None;
+        }
+
+        // Totals satisfy a linear recurrence no longer than the graph size
+        let terms_count = 2 * self.values.len() + 2;
+        let mut counts = self
+            .initial_counts
+            .iter()
+            .map(|&count| count % modulus)
+            .collect_vec();
+        let mut totals = Vec::with_capacity(terms_count);
+
+        for blink in 0..terms_count {
+            totals.push(
+                counts
+                    .iter()
+                    .fold(0, |total: u64, &count| total.addm(count, &modulus)),
+            );
+
+            if blink as u64 == blinks {
+                return Some(totals[blink]);
+            }
+
+            let mut next_counts = vec![0; counts.len()];
+
+            for (&count, &(left, right)) in counts.iter().zip(&self.transitions) {
+                next_counts[left] = next_counts[left].addm(count, &modulus);
+
+                if let Some(right) = right {
+                    next_counts[right] = next_counts[right].addm(count, &modulus);
+                }
+            }
+
+            counts = next_counts;
+        }
+
+        let recurrence = berlekamp_massey(&totals, modulus)?;
+
+        // Reduce x^blinks modulo the characteristic polynomial and combine first totals
+        let order = recurrence.len();
+        let poly_mul_mod = |a: &[u64], b: &[u64]| {
+            let mut product = vec![0; a.len() + b.len() - 1];
+
+            for (i, &a) in a.iter().enumerate() {
+                for (j, &b) in b.iter().enumerate() {
+                    product[i + j] = product[i + j].addm(a.mulm(b, &modulus), &modulus);
+                }
+            }
+
+            for k in (order..product.len()).rev() {
+                let coefficient = product[k];
+
+                for (i, &r) in recurrence.iter().enumerate() {
+                    product[k - 1 - i] =
+                        product[k - 1 - i].addm(coefficient.mulm(r, &modulus), &modulus);
+                }
+            }
+
+            product.truncate(order);
+            product
+        };
+
+        if order == 0 {
+            return Some(0);
+        }
+
+        let (mut power, mut base) = (vec![1], poly_mul_mod(&[0, 1], &[1]));
+        let mut exponent = blinks;
+
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                power = poly_mul_mod(&power, &base);
+            }
+
+            base = poly_mul_mod(&base, &base);
+            exponent >>= 1;
+        }
+
+        Some(
+            power
+                .iter()
+                .zip(&totals)
+                .fold(0, |total, (&coefficient, &term)| {
+                    total.addm(coefficient.mulm(term, &modulus), &modulus)
+                }),
+        )
+    }
+}
+
+#[allow(dead_code)]
+pub fn stones_expansion_modulo(
+    input: &str,
+    blinks: u64,
+    rules: StoneRules,
+    modulus: u64,
+) -> Option<u64> {
+    // Fast-forward only works with a finite stone values graph
+    stones_transition_graph(input, rules, 1 << 16)?.stones_count_modulo(blinks, modulus)
 }
 
 // ------------------------------------------------------------------------------------------------
 // Functions
 
-fn stones_expansion<const BLINKS: u8>(input: &str) -> u64 {
+fn blink_stones(stones: &mut FxHashMap<u64, u128>, rules: StoneRules) -> Option<()> {
     #[inline]
-    fn add_count(map: &mut FxHashMap<u64, u64>, stone: u64, count: u64) {
-        *map.entry(stone).or_default() += count;
+    fn add_count(map: &mut FxHashMap<u64, u128>, stone: u64, count: u128) -> Option<()> {
+        let stone_count = map.entry(stone).or_default();
+        *stone_count = stone_count.checked_add(count)?;
+
+        Some(())
     }
 
-    let mut stones = FxHashMap::with_capacity(BLINKS as usize * BLINKS as usize);
-    parse_numbers_whitespace(input).for_each(|stone| *stones.entry(stone).or_default() += 1);
+    // Blink every stone once, None on overflow
+    for (stone, count) in stones.drain().collect_vec() {
+        let (left_stone, right_stone) = rules.blink(stone)?;
 
-    // Map stones to counts and blink many times
-    for _ in 0..BLINKS {
-        for (stone, count) in stones.drain().collect_vec() {
-            if stone == 0 {
-                add_count(&mut stones, 1, count);
-            } else {
-                let digits_count = stone.ilog10() + 1;
-
-                if digits_count % 2 == 0 {
-                    let digits_pow = 10_u64.pow(digits_count / 2);
-
-                    add_count(&mut stones, stone / digits_pow, count);
-                    add_count(&mut stones, stone % digits_pow, count);
-                } else {
-                    add_count(&mut stones, stone * 2024, count);
-                }
+        add_count(stones, left_stone, count)?;
+
+        if let Some(right_stone) = right_stone {
+            add_count(stones, right_stone, count)?;
+        }
+    }
+
+    Some(())
+}
+
+fn berlekamp_massey(terms: &[u64], modulus: u64) -> Option<Vec<u64>> {
+    // Shortest recurrence terms[n] = sum(recurrence[i] * terms[n - 1 - i])
+    let (mut connection, mut previous_connection) = (vec![1], vec![1]);
+    let (mut length, mut shift, mut previous_discrepancy) = (0, 1, 1);
+
+    for n in 0..terms.len() {
+        let discrepancy = connection
+            .iter()
+            .take(length + 1)
+            .enumerate()
+            .fold(0, |discrepancy, (i, &c)| {
+                discrepancy.addm(c.mulm(terms[n - i], &modulus), &modulus)
+            });
+
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+
+        let coefficient = discrepancy.mulm(previous_discrepancy.invm(&modulus)?, &modulus);
+        let old_connection = connection.clone();
+
+        connection.resize(connection.len().max(previous_connection.len() + shift), 0);
+
+        for (i, &p) in previous_connection.iter().enumerate() {
+            connection[i + shift] =
+                connection[i + shift].subm(coefficient.mulm(p, &modulus), &modulus);
+        }
+
+        if 2 * length <= n {
+            length = n + 1 - length;
+            previous_connection = old_connection;
+            previous_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            shift += 1;
+        }
+    }
+
+    connection.resize(length + 1, 0);
+    Some(
+        connection[1..]
+            .iter()
+            .map(|&c| 0.subm(c, &modulus))
+            .collect(),
+    )
+}
+
+fn is_prime(number: u64) -> bool {
+    // Deterministic Miller-Rabin, these bases cover every u64
+    const BASES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+    if number < 2 {
+        return false;
+    }
+
+    if let Some(&base) = BASES.iter().find(|&&base| number.is_multiple_of(base)) {
+        return number == base;
+    }
+
+    let odd_factor = (number - 1) >> (number - 1).trailing_zeros();
+
+    BASES.iter().all(|&base| {
+        let mut power = base.powm(odd_factor, &number);
+        let mut exponent = odd_factor;
+
+        if power == 1 {
+            return true;
+        }
+
+        while exponent < number - 1 {
+            if power == number - 1 {
+                return true;
+            }
+
+            power = power.mulm(power, &number);
+            exponent <<= 1;
+        }
+
+        false
+    })
+}
+
+// ------------------------------------------------------------------------------------------------
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overflowing_and_invalid_rules_blink_to_none() {
+        let rules = StoneRules {
+            multiplier: u64::MAX,
+            ..StoneRules::default()
+        };
+
+        assert_eq!(rules.blink(1), Some((u64::MAX, None)));
+        assert_eq!(rules.blink(2), None);
+        assert_eq!(stones_expansion("2", 1, rules), None);
+        assert!(stones_transition_graph("2", rules, 16).is_none());
+        assert!(blink_states("2", rules).nth(1).is_none());
+
+        for split_base in [0, 1] {
+            let rules = StoneRules {
+                split_base,
+                ..StoneRules::default()
+            };
+
+            assert_eq!(rules.blink(7), None);
+            assert_eq!(stones_expansion("7", 1, rules), None);
+        }
+    }
+
+    #[test]
+    fn empty_input_has_empty_graph() {
+        let graph =
+            stones_transition_graph("", StoneRules::default(), 16).expect("Expected empty graph");
+
+        assert!(graph.values.is_empty() && graph.transitions.is_empty());
+        assert_eq!(graph.closure_blinks, 0);
+        assert_eq!(
+            stones_expansion_modulo("", 75, StoneRules::default(), 1_000_000_007),
+            Some(0)
+        );
+        assert_eq!(stones_expansion("", 75, StoneRules::default()), Some(0));
+    }
+
+    #[test]
+    fn recurrence_fast_forward_matches_expansion() {
+        let rules = StoneRules::default();
+
+        for modulus in [2, 1_000_000_007, 998_244_353, (1 << 61) - 1] {
+            for blinks in [0, 1, 25, 75, 200] {
+                let count = stones_expansion("125 17", blinks, rules).expect("Expected count");
+
+                assert_eq!(
+                    stones_expansion_modulo("125 17", blinks as u64, rules, modulus),
+                    Some(u64::try_from(count % u128::from(modulus)).expect("Expected remainder"))
+                );
             }
         }
     }
 
-    stones.values().sum()
+    #[test]
+    fn non_prime_moduli_fast_forward_to_none() {
+        for modulus in [0, 1, 4, 6, 1000, 1 << 20, 1_000_000_007 * 3] {
+            assert_eq!(
+                stones_expansion_modulo("125 17", 1_000_000, StoneRules::default(), modulus),
+                None
+            );
+        }
+
+        assert!(
+            stones_expansion_modulo("125 17", 1_000_000, StoneRules::default(), 1_000_000_007)
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn blink_states_track_example_stones() {
+        let rules = StoneRules::default();
+        let states = blink_states("125 17", rules).take(26).collect_vec();
+
+        assert_eq!(
+            states
+                .iter()
+                .take(7)
+                .map(|state| state.stones_count)
+                .collect_vec(),
+            [2, 3, 4, 5, 9, 13, 22]
+        );
+        assert_eq!(
+            states[..3]
+                .iter()
+                .map(|state| (
+                    state.blink,
+                    state.distinct_stones_count,
+                    state.largest_stone,
+                    state.initial_stones_counts.clone()
+                ))
+                .collect_vec(),
+            [
+                (0, 2, 125, vec![1, 1]),
+                (1, 3, 253_000, vec![1, 2]),
+                (2, 4, 14168, vec![2, 2]),
+            ]
+        );
+
+        for state in &states {
+            assert_eq!(
+                state.initial_stones_counts,
+                [
+                    stones_expansion("125", state.blink, rules),
+                    stones_expansion("17", state.blink, rules)
+                ]
+                .map(|count| count.expect("Expected count"))
+            );
+        }
+
+        assert_eq!(states[25].stones_count, 55312);
+    }
+
+    #[test]
+    fn blink_states_stay_done_after_overflow() {
+        let rules = StoneRules {
+            multiplier: u64::MAX,
+            ..StoneRules::default()
+        };
+        let mut states = blink_states("2", rules);
+
+        assert!(states.next().is_some());
+        assert!((0..3).all(|_| states.next().is_none()));
+    }
 }
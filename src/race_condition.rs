@@ -1,9 +1,12 @@
+use std::collections::BTreeMap;
+
 use grid::Grid;
 use itertools::{Itertools, iproduct};
-use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
 use crate::random_utils::{
     bytes_grid,
+    grid_mask::GridMask,
     pos::{GridPosGet, Pos},
 };
 
@@ -12,96 +15,363 @@ use crate::random_utils::{
 
 pub fn best_2_picos_cheat_paths_count(input: &str) -> usize {
     // Find best cheated paths with cheat limit of 2 picoseconds
-    best_cheat_paths_count::<2>(input)
+    best_cheat_paths_count(input, 2, 100)
 }
 
 pub fn best_20_picos_cheat_paths_count(input: &str) -> usize {
     // Find best cheated paths with cheat limit of 20 picoseconds
-    best_cheat_paths_count::<20>(input)
+    best_cheat_paths_count(input, 20, 100)
+}
+
+#[allow(dead_code)]
+pub struct Cheat {
+    pub start: Pos,
+    pub end: Pos,
+    pub time_save: usize,
+}
+
+#[allow(dead_code)]
+pub fn cheats_time_save_histogram(input: &str, cheat_radius: usize) -> BTreeMap<usize, usize> {
+    let racetrack = Racetrack::new(input);
+    let mut histogram = BTreeMap::new();
+
+    // Count cheats by time saved
+    for (_, _, time_save) in racetrack.cheats(&cheat_jumps(cheat_radius), 1) {
+        *histogram.entry(time_save).or_default() += 1;
+    }
+
+    histogram
+}
+
+#[allow(dead_code)]
+pub fn cheats_list(input: &str, cheat_radius: usize, minimum_time_save: usize) -> Vec<Cheat> {
+    let racetrack = Racetrack::new(input);
+
+    // List cheats with their start and end positions
+    racetrack
+        .cheats(&cheat_jumps(cheat_radius), minimum_time_save)
+        .map(|(start, end, time_save)| Cheat {
+            start,
+            end,
+            time_save,
+        })
+        .collect()
 }
 
 // ------------------------------------------------------------------------------------------------
 // Functions
 
-fn best_cheat_paths_count<const CHEAT_RADIUS: isize>(input: &str) -> usize {
-    // Get racetrack grid and find single path
-    let mut racetrack = bytes_grid(input);
+struct Racetrack {
+    track: Vec<Pos>,
+    start_costs: Grid<usize>,
+    end_costs: Grid<usize>,
+    best_time: usize,
+}
+
+impl Racetrack {
+    fn new(input: &str) -> Self {
+        let mut racetrack = bytes_grid(input);
+
+        let (start, end) = (
+            racetrack
+                .indexed_iter()
+                .find(|&(_, &b)| b == b'S')
+                .expect("Expected start position")
+                .0,
+            racetrack
+                .indexed_iter()
+                .find(|&(_, &b)| b == b'E')
+                .expect("Expected end position")
+                .0,
+        );
+
+        racetrack[start] = b'.';
+        racetrack[end] = b'.';
+
+        let (start, end) = (Pos::from(start), Pos::from(end));
+        let mut start_costs = Grid::init(racetrack.rows(), racetrack.cols(), usize::MAX);
+        let mut end_costs = start_costs.clone();
+
+        // Walk single path when possible, otherwise use distances from start and to end
+        if let Some(single_path) = Self::single_path(&racetrack, start, end) {
+            let best_time = single_path.len() - 1;
+
+            for (cost, &position) in single_path.iter().enumerate() {
+                *start_costs.pos_index_mut(position) = cost;
+                *end_costs.pos_index_mut(position) = best_time - cost;
+            }
+
+            Self {
+                track: single_path,
+                start_costs,
+                end_costs,
+                best_time,
+            }
+        } else {
+            let track = Self::bfs_costs(&racetrack, start, &mut start_costs);
+            Self::bfs_costs(&racetrack, end, &mut end_costs);
+            let best_time = *start_costs.pos_index(end);
+
+            assert_ne!(best_time, usize::MAX, "Expected path");
+
+            Self {
+                track,
+                start_costs,
+                end_costs,
+                best_time,
+            }
+        }
+    }
+
+    fn single_path(racetrack: &Grid<u8>, mut position: Pos, end: Pos) -> Option<Vec<Pos>> {
+        let mut visited = GridMask::new(racetrack.size());
+        let mut single_path = Vec::with_capacity(position.manhattan_distance(end));
+        single_path.push(position);
+        visited.set_true(position);
+
+        // Any branch or dead end means no single path
+        while position != end {
+            position = position
+                .adjacent()
+                .filter(|&pos| racetrack.pos_get(pos) == Some(&b'.') && !visited[pos])
+                .exactly_one()
+                .ok()?;
+
+            single_path.push(position);
+            visited.set_true(position);
+        }
 
-    let (position, end) = (
-        racetrack
-            .indexed_iter()
-            .find(|&(_, &b)| b == b'S')
-            .expect("Expected start position")
-            .0,
         racetrack
             .indexed_iter()
-            .find(|&(_, &b)| b == b'E')
-            .expect("Expected end position")
-            .0,
-    );
+            .all(|(pos, &b)| b != b'.' || visited[Pos::from(pos)])
+            .then_some(single_path)
+    }
+
+    fn bfs_costs(racetrack: &Grid<u8>, from: Pos, costs: &mut Grid<usize>) -> Vec<Pos> {
+        let mut reached = vec![from];
+        *costs.pos_index_mut(from) = 0;
+        let mut next_position = 0;
 
-    racetrack[position] = b'.';
-    racetrack[end] = b'.';
+        // Breadth first distances over open track
+        while let Some(&position) = reached.get(next_position) {
+            next_position += 1;
+            let cost = *costs.pos_index(position) + 1;
 
-    let (mut position, end) = (Pos::from(position), Pos::from(end));
-    let mut single_path = Vec::with_capacity(position.manhattan_distance(end));
-    single_path.push(position);
+            for next in position.adjacent() {
+                if racetrack.pos_get(next) == Some(&b'.') && *costs.pos_index(next) == usize::MAX {
+                    *costs.pos_index_mut(next) = cost;
+                    reached.push(next);
+                }
+            }
+        }
 
-    while position != end {
-        *racetrack.pos_index_mut(position) = b'#';
+        reached
+    }
 
-        position = position
-            .adjacent()
-            .find(|&pos| racetrack.pos_index(pos) == &b'.')
-            .expect("Expected path");
+    fn cheats(
+        &self,
+        cheat_jumps: &[Pos],
+        minimum_time_save: usize,
+    ) -> impl Iterator<Item = (Pos, Pos, usize)> {
+        // Cheat from any track position to any other one in reach
+        self.track.iter().flat_map(move |&position| {
+            let start_cost = *self.start_costs.pos_index(position);
 
-        single_path.push(position);
+            cheat_jumps.iter().filter_map(move |&jump| {
+                let cheat_position = position + jump;
+                let time_save = self.cheat_time_save(start_cost, position, cheat_position)?;
+
+                (time_save >= minimum_time_save).then_some((position, cheat_position, time_save))
+            })
+        })
     }
 
-    // Create second grid of path costs for fast lookup
-    let mut path_costs = Grid::new(racetrack.rows(), racetrack.cols());
-    path_costs.fill(usize::MAX);
+    fn cheat_time_save(
+        &self,
+        start_cost: usize,
+        position: Pos,
+        cheat_position: Pos,
+    ) -> Option<usize> {
+        let end_cost = *self
+            .end_costs
+            .pos_get(cheat_position)
+            .filter(|&&end_cost| end_cost != usize::MAX)?;
 
-    for (cost, &position) in single_path.iter().enumerate() {
-        *path_costs.pos_index_mut(position) = cost;
+        self.best_time
+            .checked_sub(start_cost + position.manhattan_distance(cheat_position) + end_cost)
     }
+}
+
+fn cheat_jumps(cheat_radius: usize) -> Vec<Pos> {
+    #[allow(clippy::cast_possible_wrap)]
+    let cheat_radius = cheat_radius as isize;
 
     // Prepare possible cheat jumps
-    let possible_cheat_jumps =
-        iproduct!(-CHEAT_RADIUS..=CHEAT_RADIUS, -CHEAT_RADIUS..=CHEAT_RADIUS)
-            .filter(|&(dx, dy)| {
-                let (dx, dy) = (dx.abs(), dy.abs());
+    iproduct!(-cheat_radius..=cheat_radius, -cheat_radius..=cheat_radius)
+        .filter(|&(dx, dy)| {
+            let (dx, dy) = (dx.abs(), dy.abs());
 
-                (dx > 1 || dy > 1) && dx + dy <= CHEAT_RADIUS
-            })
-            .map(Pos::from)
-            .collect_vec();
-    let minimum_time_save = 100;
+            (2..=cheat_radius).contains(&(dx + dy))
+        })
+        .map(Pos::from)
+        .collect_vec()
+}
+
+fn best_cheat_paths_count(input: &str, cheat_radius: usize, minimum_time_save: usize) -> usize {
+    let racetrack = Racetrack::new(input);
+    let possible_cheat_jumps = cheat_jumps(cheat_radius);
 
     // Count cheated paths with minimum time save
-    single_path
+    racetrack
+        .track
         .par_iter()
-        .take(single_path.len() - minimum_time_save)
-        .enumerate()
-        .map(|(cost, &position)| {
+        .map(|&position| {
+            let start_cost = *racetrack.start_costs.pos_index(position);
+
             possible_cheat_jumps
                 .iter()
-                .map(|&jump| {
-                    let cheat_position = position + jump;
-
-                    usize::from(
-                        path_costs
-                            .pos_get(cheat_position)
-                            .is_some_and(|&cheat_cost| {
-                                cheat_cost != usize::MAX
-                                    && cheat_cost
-                                        .saturating_sub(cost)
-                                        .saturating_sub(position.manhattan_distance(cheat_position))
-                                        >= minimum_time_save
-                            }),
-                    )
+                .filter(|&&jump| {
+                    racetrack
+                        .cheat_time_save(start_cost, position, position + jump)
+                        .is_some_and(|time_save| time_save >= minimum_time_save)
                 })
-                .sum::<usize>()
+                .count()
         })
         .sum()
 }
+
+// ------------------------------------------------------------------------------------------------
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    fn bfs_distances(racetrack: &Grid<u8>, from: Pos, through_walls: bool) -> Grid<usize> {
+        let mut distances = Grid::init(racetrack.rows(), racetrack.cols(), usize::MAX);
+        *distances.pos_index_mut(from) = 0;
+        let mut positions = VecDeque::from([from]);
+
+        while let Some(position) = positions.pop_front() {
+            let distance = *distances.pos_index(position) + 1;
+
+            for next in position.adjacent() {
+                if racetrack
+                    .pos_get(next)
+                    .is_some_and(|&b| through_walls || b != b'#')
+                    && *distances.pos_index(next) == usize::MAX
+                {
+                    *distances.pos_index_mut(next) = distance;
+                    positions.push_back(next);
+                }
+            }
+        }
+
+        distances
+    }
+
+    fn brute_force_histogram(input: &str, cheat_radius: usize) -> BTreeMap<usize, usize> {
+        let racetrack = bytes_grid(input);
+        let find = |tile| {
+            Pos::from(
+                racetrack
+                    .indexed_iter()
+                    .find(|&(_, &b)| b == tile)
+                    .expect("Expected tile")
+                    .0,
+            )
+        };
+        let (start_distances, end_distances) = (
+            bfs_distances(&racetrack, find(b'S'), false),
+            bfs_distances(&racetrack, find(b'E'), false),
+        );
+        let best_time = *start_distances.pos_index(find(b'E'));
+        let mut histogram = BTreeMap::new();
+
+        // Walk through anything for up to radius steps from every reachable track position
+        for (position, &start_distance) in start_distances.indexed_iter() {
+            if start_distance == usize::MAX {
+                continue;
+            }
+
+            let cheat_distances = bfs_distances(&racetrack, Pos::from(position), true);
+
+            for (cheat_position, &cheat_distance) in cheat_distances.indexed_iter() {
+                let end_distance = end_distances[cheat_position];
+
+                if cheat_distance <= cheat_radius
+                    && end_distance != usize::MAX
+                    && start_distance + cheat_distance + end_distance < best_time
+                {
+                    *histogram
+                        .entry(best_time - start_distance - cheat_distance - end_distance)
+                        .or_default() += 1;
+                }
+            }
+        }
+
+        histogram
+    }
+
+    #[test]
+    fn diagonal_cheats_match_brute_force() {
+        let input = "\
+########
+#S.#####
+##.#####
+#..#####
+#.#....#
+#...##E#
+########
+";
+
+        for cheat_radius in [2, 3, 20] {
+            assert_eq!(
+                cheats_time_save_histogram(input, cheat_radius),
+                brute_force_histogram(input, cheat_radius)
+            );
+        }
+
+        assert!(
+            cheats_list(input, 2, 4)
+                .iter()
+                .any(|cheat| cheat.start == Pos::new(3, 2) && cheat.end == Pos::new(4, 3))
+        );
+    }
+
+    #[test]
+    fn branched_track_cheats_match_brute_force() {
+        let input = "\
+###########
+#S..#.....#
+#.#.#.###.#
+#.#...#...#
+#.#####.#.#
+#...#...#E#
+###.#.###.#
+#.....#...#
+###########
+";
+        let mut racetrack = bytes_grid(input);
+        racetrack[(1, 1)] = b'.';
+        racetrack[(5, 9)] = b'.';
+
+        assert!(Racetrack::single_path(&racetrack, Pos::new(1, 1), Pos::new(5, 9)).is_none());
+
+        for cheat_radius in [2, 3, 6, 20] {
+            assert_eq!(
+                cheats_time_save_histogram(input, cheat_radius),
+                brute_force_histogram(input, cheat_radius)
+            );
+            assert_eq!(
+                best_cheat_paths_count(input, cheat_radius, 4),
+                brute_force_histogram(input, cheat_radius)
+                    .range(4..)
+                    .map(|(_, &count)| count)
+                    .sum::<usize>()
+            );
+        }
+    }
+}
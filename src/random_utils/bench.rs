@@ -0,0 +1,73 @@
+use std::{fmt::Debug, time::Instant};
+
+use grid::Grid;
+use itertools::Itertools;
+
+// ------------------------------------------------------------------------------------------------
+// Inputs
+
+pub struct XorShift(u64);
+
+impl XorShift {
+    pub const fn new() -> Self {
+        Self(0x2545_f491_4f6c_dd1d)
+    }
+
+    pub fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+
+        usize::try_from(self.0 % bound as u64).expect("Expected small random value")
+    }
+}
+
+pub fn bench_inputs<Generate>(puzzle: &str, generate: Generate) -> Vec<(String, String)>
+where
+    Generate: FnOnce(&mut XorShift) -> Vec<(String, String)>,
+{
+    // Puzzle input when available, otherwise generated ones
+    crate::RESOURCES_DIR
+        .get_file(format!("{puzzle}.in"))
+        .map_or_else(
+            || generate(&mut XorShift::new()),
+            |file| {
+                vec![(
+                    "puzzle input".to_owned(),
+                    file.contents_utf8()
+                        .expect("Resource is not UTF-8")
+                        .to_owned(),
+                )]
+            },
+        )
+}
+
+pub fn grid_string(grid: &Grid<u8>) -> String {
+    grid.iter_rows()
+        .map(|row| row.map(|&b| char::from(b)).collect::<String>())
+        .join("\n")
+}
+
+// ------------------------------------------------------------------------------------------------
+// Timing
+
+pub fn best_of_runs<R, Solution>(name: &str, solution: Solution) -> R
+where
+    R: Debug,
+    Solution: Fn() -> R,
+{
+    // Best of a few runs
+    let (result, microseconds) = (0..10)
+        .map(|_| {
+            let now = Instant::now();
+            let result = solution();
+
+            (result, now.elapsed().as_micros())
+        })
+        .min_by_key(|&(_, microseconds)| microseconds)
+        .expect("Expected runs");
+
+    println!("{name} -> {result:?} ({microseconds}μs)");
+
+    result
+}
@@ -5,6 +5,8 @@ use itertools::Itertools;
 use lexical_core::FromLexical;
 use rustc_hash::{FxBuildHasher, FxHashMap, FxHashSet};
 
+#[cfg(test)]
+pub mod bench;
 pub mod grid_mask;
 pub mod pos;
 
@@ -1,10 +1,13 @@
-use std::rc::Rc;
+use std::{cmp::Reverse, collections::BinaryHeap, mem::replace, rc::Rc};
 
+use grid::Grid;
 use itertools::Itertools;
 use pathfinding::directed::astar::{astar, astar_bag};
+use rustc_hash::FxHashSet;
 
 use crate::random_utils::{
-    bytes_grid,
+    FxHashWithCapacity, bytes_grid,
+    grid_mask::GridMask,
     pos::{Dir, GridPosGet, Pos},
 };
 
@@ -12,25 +15,343 @@ use crate::random_utils::{
 // Exports
 
 pub fn maze_best_path_score(input: &str) -> usize {
-    // Use A* to find shortest path (lowest score)
-    maze_astar(input, astar).expect("Expected shortest path").1
+    // Use Dijkstra on junctions graph to find shortest path (lowest score)
+    MazeGraph::new(input, MazeCosts::default())
+        .best_score_seats()
+        .expect("Expected shortest path")
+        .0
 }
 
 pub fn maze_best_seats_count(input: &str) -> usize {
-    // Use A* to find all shortest paths and then count unique positions
-    maze_astar(input, astar_bag)
+    // Use Dijkstra on junctions graph to find all shortest paths and count their tiles
+    MazeGraph::new(input, MazeCosts::default())
+        .best_score_seats()
         .expect("Expected shortest paths")
+        .1
+}
+
+#[derive(Clone, Copy)]
+pub struct MazeCosts {
+    pub forward: usize,
+    pub turn: usize,
+    pub u_turn: Option<usize>,
+    pub start_direction: Dir,
+    pub end_direction: Option<Dir>,
+}
+
+impl Default for MazeCosts {
+    fn default() -> Self {
+        // Puzzle rules, turning back is never worth it
+        Self {
+            forward: 1,
+            turn: 1000,
+            u_turn: None,
+            start_direction: Dir::E,
+            end_direction: None,
+        }
+    }
+}
+
+impl MazeCosts {
+    fn move_cost(self, facing: Dir, direction: Dir) -> Option<usize> {
+        if direction == facing {
+            Some(self.forward)
+        } else if direction == facing.opposite() {
+            self.u_turn.map(|u_turn| u_turn + self.forward)
+        } else {
+            Some(self.turn + self.forward)
+        }
+    }
+
+    fn lower_bound(self, (position, direction): (Pos, Dir), end_position: Pos) -> usize {
+        // Every step moves once and every needed direction change costs at least a turn
+        let needed_directions = [
+            (end_position.x > position.x).then_some(Dir::S),
+            (end_position.x < position.x).then_some(Dir::N),
+            (end_position.y > position.y).then_some(Dir::E),
+            (end_position.y < position.y).then_some(Dir::W),
+        ];
+        let (facing_needed, opposite_needed) = (
+            needed_directions.contains(&Some(direction)),
+            needed_directions.contains(&Some(direction.opposite())),
+        );
+        let needed_count = needed_directions.iter().flatten().count();
+
+        let turns_cost = match (needed_count, facing_needed, opposite_needed) {
+            (0, _, _) | (1, true, _) => 0,
+            (_, false, true) => self
+                .u_turn
+                .map_or(2 * self.turn, |u_turn| u_turn.min(2 * self.turn)),
+            _ => self.turn,
+        };
+
+        position.manhattan_distance(end_position) * self.forward + turns_cost
+    }
+}
+
+#[allow(dead_code)]
+pub fn maze_best_path_score_costs(input: &str, costs: MazeCosts) -> Option<usize> {
+    Some(maze_astar(input, costs, astar)?.1)
+}
+
+#[allow(dead_code)]
+pub fn maze_best_seats_count_costs(input: &str, costs: MazeCosts) -> Option<usize> {
+    Some(
+        maze_astar(input, costs, astar_bag)?
+            .0
+            .flatten()
+            .map(|(pos, _)| pos)
+            .unique()
+            .count(),
+    )
+}
+
+#[allow(dead_code)]
+pub fn maze_best_path(input: &str, costs: MazeCosts) -> Option<Vec<(Pos, Dir)>> {
+    // Keep one shortest path as position and facing direction steps, None if end is unreachable
+    Some(maze_astar(input, costs, astar)?.0)
+}
+
+#[allow(dead_code)]
+pub fn maze_best_tiles(input: &str, costs: MazeCosts) -> Option<GridMask> {
+    let maze = bytes_grid(input);
+    let mut best_tiles = GridMask::new(maze.size());
+
+    // Mark every position on any shortest path
+    maze_astar(input, costs, astar_bag)?
         .0
         .flatten()
-        .map(|(pos, _)| pos)
-        .unique()
-        .count()
+        .for_each(|(position, _)| best_tiles[position] = true);
+
+    Some(best_tiles)
+}
+
+#[allow(dead_code)]
+pub fn maze_best_tiles_string(input: &str, costs: MazeCosts) -> Option<String> {
+    let best_tiles = maze_best_tiles(input, costs)?;
+
+    // Draw maze with best tiles as O
+    Some(
+        bytes_grid(input)
+            .iter_rows()
+            .enumerate()
+            .map(|(x, row)| {
+                row.enumerate()
+                    .map(|(y, &tile)| {
+                        if best_tiles[Pos::from((x, y))] {
+                            'O'
+                        } else {
+                            char::from(tile)
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .join("\n"),
+    )
+}
+
+struct MazeEdge {
+    to: usize,
+    out_direction: Dir,
+    in_direction: Dir,
+    cost: usize,
+    tiles: Vec<Pos>,
+}
+
+pub struct MazeGraph {
+    costs: MazeCosts,
+    nodes: Vec<Pos>,
+    nodes_edges: Vec<Vec<MazeEdge>>,
+    start: usize,
+    end: usize,
+}
+
+impl MazeGraph {
+    pub fn new(input: &str, costs: MazeCosts) -> Self {
+        const DIRECTIONS: [Dir; 4] = [Dir::S, Dir::E, Dir::N, Dir::W];
+
+        let maze = bytes_grid(input);
+        let open = |position| maze.pos_get(position).is_some_and(|&tile| tile != b'#');
+        let open_directions = |position: Pos| {
+            DIRECTIONS
+                .into_iter()
+                .filter(move |&direction| open(position.move_dir(direction)))
+        };
+
+        // Junctions are start, end and open tiles not in the middle of a corridor
+        let mut nodes_indices = Grid::init(maze.rows(), maze.cols(), usize::MAX);
+        let mut nodes = Vec::new();
+
+        for (position, &tile) in maze.indexed_iter() {
+            let position = Pos::from(position);
+
+            if tile == b'S'
+                || tile == b'E'
+                || (tile != b'#' && open_directions(position).count() != 2)
+            {
+                *nodes_indices.pos_index_mut(position) = nodes.len();
+                nodes.push(position);
+            }
+        }
+
+        // Walk corridors out of every junction, paying for bends along the way
+        let nodes_edges = nodes
+            .iter()
+            .map(|&node| {
+                open_directions(node)
+                    .flat_map(|out_direction| {
+                        let (mut position, mut direction) =
+                            (node.move_dir(out_direction), out_direction);
+                        let mut tiles = vec![position];
+                        let mut cost = 0;
+
+                        // Turning back right after leaving is the only useful corridor U-turn
+                        let bounce = (*nodes_indices.pos_index(position) == usize::MAX)
+                            .then(|| costs.move_cost(out_direction, out_direction.opposite()))
+                            .flatten()
+                            .map(|bounce_cost| MazeEdge {
+                                to: *nodes_indices.pos_index(node),
+                                out_direction,
+                                in_direction: out_direction.opposite(),
+                                cost: bounce_cost,
+                                tiles: vec![position],
+                            });
+
+                        while *nodes_indices.pos_index(position) == usize::MAX {
+                            let next_direction =
+                                [direction, direction.rotate_ccw(), direction.rotate_cw()]
+                                    .into_iter()
+                                    .find(|&next_direction| open(position.move_dir(next_direction)))
+                                    .expect("Expected corridor to continue");
+
+                            cost += costs
+                                .move_cost(direction, next_direction)
+                                .expect("Expected corridor without U-turns");
+                            position.move_dir_mut(next_direction);
+                            direction = next_direction;
+                            tiles.push(position);
+                        }
+
+                        [
+                            Some(MazeEdge {
+                                to: *nodes_indices.pos_index(position),
+                                out_direction,
+                                in_direction: direction,
+                                cost,
+                                tiles,
+                            }),
+                            bounce,
+                        ]
+                    })
+                    .flatten()
+                    .collect_vec()
+            })
+            .collect_vec();
+
+        let node_with = |tile| {
+            maze.indexed_iter()
+                .find(|&(_, &t)| t == tile)
+                .map(|(position, _)| *nodes_indices.pos_index(Pos::from(position)))
+        };
+
+        Self {
+            costs,
+            start: node_with(b'S').expect("Expected starting position"),
+            end: node_with(b'E').expect("Expected ending position"),
+            nodes,
+            nodes_edges,
+        }
+    }
+
+    pub fn best_score_seats(&self) -> Option<(usize, usize)> {
+        // Dense Dijkstra over (junction, facing direction) states
+        let states_count = self.nodes.len() * 4;
+        let mut scores = vec![usize::MAX; states_count];
+        let mut predecessors: Vec<Vec<(usize, usize)>> = vec![Vec::new(); states_count];
+
+        let start_state = self.start * 4 + usize::from(self.costs.start_direction);
+        scores[start_state] = 0;
+        let mut states_queue = BinaryHeap::from([Reverse((0, start_state))]);
+        let mut best_score = usize::MAX;
+
+        while let Some(Reverse((score, state))) = states_queue.pop() {
+            if score > best_score {
+                break;
+            }
+
+            if score > scores[state] {
+                continue;
+            }
+
+            let (node, facing) = (state / 4, Dir::from(state % 4));
+
+            if node == self.end && self.costs.end_direction.is_none_or(|end| end == facing) {
+                best_score = score;
+                continue;
+            }
+
+            for (edge_index, edge) in self.nodes_edges[node].iter().enumerate() {
+                let Some(leave_cost) = self.costs.move_cost(facing, edge.out_direction) else {
+                    continue;
+                };
+
+                let (next_state, next_score) = (
+                    edge.to * 4 + usize::from(edge.in_direction),
+                    score + leave_cost + edge.cost,
+                );
+
+                if next_score < scores[next_state] {
+                    scores[next_state] = next_score;
+                    predecessors[next_state].clear();
+                    predecessors[next_state].push((state, edge_index));
+                    states_queue.push(Reverse((next_score, next_state)));
+                } else if next_score == scores[next_state] {
+                    predecessors[next_state].push((state, edge_index));
+                }
+            }
+        }
+
+        if best_score == usize::MAX {
+            return None;
+        }
+
+        // Walk back from best end states marking tiles of all shortest paths
+        let mut best_tiles = FxHashSet::with_capacity(self.nodes.len());
+        let mut visited_states = vec![false; states_count];
+        let mut states_stack = (self.end * 4..self.end * 4 + 4)
+            .filter(|&state| {
+                scores[state] == best_score
+                    && self
+                        .costs
+                        .end_direction
+                        .is_none_or(|end| usize::from(end) == state % 4)
+            })
+            .collect_vec();
+        best_tiles.insert(self.nodes[self.start]);
+
+        while let Some(state) = states_stack.pop() {
+            if replace(&mut visited_states[state], true) {
+                continue;
+            }
+
+            for &(previous_state, edge_index) in &predecessors[state] {
+                best_tiles.extend(&self.nodes_edges[previous_state / 4][edge_index].tiles);
+                states_stack.push(previous_state);
+            }
+        }
+
+        Some((best_score, best_tiles.len()))
+    }
 }
 
 // ------------------------------------------------------------------------------------------------
 // Functions
 
-fn maze_astar<Astar, Solution>(input: &str, astar_function: Astar) -> Option<Solution>
+fn maze_astar<Astar, Solution>(
+    input: &str,
+    costs: MazeCosts,
+    astar_function: Astar,
+) -> Option<Solution>
 where
     Astar: Fn(
         &(Pos, Dir),
@@ -42,7 +363,7 @@ where
     let maze = Rc::new(bytes_grid(input));
 
     // Get start (position, direction) and end position
-    let start_direction = Dir::E;
+    let start_direction = costs.start_direction;
     let (start_position, end_position) = (
         Pos::from(
             maze.indexed_iter()
@@ -68,12 +389,16 @@ where
 
             Box::new(
                 [
-                    (direction.rotate_ccw(), 1001),
-                    (direction, 1),
-                    (direction.rotate_cw(), 1001),
+                    Some((direction.rotate_ccw(), costs.turn + costs.forward)),
+                    Some((direction, costs.forward)),
+                    Some((direction.rotate_cw(), costs.turn + costs.forward)),
+                    costs
+                        .u_turn
+                        .map(|u_turn| (direction.opposite(), u_turn + costs.forward)),
                 ]
-                .map(|(dir, cost)| (position.move_dir(dir), dir, cost))
                 .into_iter()
+                .flatten()
+                .map(move |(dir, cost)| (position.move_dir(dir), dir, cost))
                 .filter_map(move |(next_pos, next_dir, cost)| {
                     maze.pos_get(next_pos).and_then(|&c| {
                         if c == b'#' {
@@ -85,9 +410,215 @@ where
                 }),
             )
         }),
-        // Heuristic function uses Manhattan distance to end position
-        Box::new(move |&(position, _)| position.manhattan_distance(end_position)),
+        // Heuristic function uses Manhattan distance and needed turns to end position
+        Box::new(move |&state| costs.lower_bound(state, end_position)),
         // Goal function
-        Box::new(move |&(position, _)| position == end_position),
+        Box::new(move |&(position, direction)| {
+            position == end_position && costs.end_direction.is_none_or(|end| end == direction)
+        }),
     )
 }
+
+// ------------------------------------------------------------------------------------------------
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random_utils::bench::{XorShift, bench_inputs, best_of_runs, grid_string};
+
+    const EXAMPLES: [&str; 2] = [
+        "\
+###############
+#.......#....E#
+#.#.###.#.###.#
+#.....#.#...#.#
+#.###.#####.#.#
+#.#.#.......#.#
+#.#.#####.###.#
+#...........#.#
+###.#.#####.#.#
+#...#.....#.#.#
+#.#.#.###.#.#.#
+#.....#...#.#.#
+#.###.#.#.#.#.#
+#S..#.....#...#
+###############
+",
+        "\
+#################
+#...#...#...#..E#
+#.#.#.#.#.#.#.#.#
+#.#.#.#...#...#.#
+#.#.#.#.###.#.#.#
+#...#.#.#.....#.#
+#.#.#.#.#.#####.#
+#.#...#.#.#.....#
+#.#.#####.#.###.#
+#.#.#.......#...#
+#.#.###.#####.###
+#.#.#...#.....#.#
+#.#.#.#####.###.#
+#.#.#.........#.#
+#.#.#.#########.#
+#S#.............#
+#################
+",
+    ];
+
+    fn costs_variants() -> [MazeCosts; 5] {
+        let costs = MazeCosts::default();
+
+        [
+            costs,
+            MazeCosts { turn: 1, ..costs },
+            MazeCosts {
+                u_turn: Some(1500),
+                ..costs
+            },
+            MazeCosts {
+                start_direction: Dir::N,
+                ..costs
+            },
+            MazeCosts {
+                end_direction: Some(Dir::E),
+                ..costs
+            },
+        ]
+    }
+
+    #[test]
+    fn maze_graph_matches_astar() {
+        for (input, answers) in EXAMPLES.into_iter().zip([(7036, 45), (11048, 64)]) {
+            assert_eq!(
+                (maze_best_path_score(input), maze_best_seats_count(input)),
+                answers
+            );
+
+            for costs in costs_variants() {
+                assert_eq!(
+                    MazeGraph::new(input, costs).best_score_seats(),
+                    maze_best_path_score_costs(input, costs)
+                        .zip(maze_best_seats_count_costs(input, costs))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn best_path_and_tiles_match_examples() {
+        let costs = MazeCosts::default();
+
+        for (input, (score, seats)) in EXAMPLES.into_iter().zip([(7036, 45), (11048, 64)]) {
+            let path = maze_best_path(input, costs).expect("Expected shortest path");
+            let path_score = path
+                .iter()
+                .tuple_windows()
+                .map(|(&(_, facing), &(_, direction))| {
+                    costs
+                        .move_cost(facing, direction)
+                        .expect("Expected allowed move")
+                })
+                .sum::<usize>();
+
+            assert_eq!(path_score, score);
+            assert_eq!(path.len(), score % 1000 + 1);
+
+            let tiles_string =
+                maze_best_tiles_string(input, costs).expect("Expected shortest paths");
+
+            assert_eq!(tiles_string.matches('O').count(), seats);
+            assert_eq!(tiles_string.matches(['S', 'E']).count(), 0);
+            assert_eq!(
+                tiles_string.replace('O', "."),
+                input.trim().replace(['S', 'E'], ".")
+            );
+        }
+
+        // Ending position is a corner reachable only facing north or east
+        let unreachable = MazeCosts {
+            end_direction: Some(Dir::S),
+            ..costs
+        };
+
+        assert_eq!(maze_best_path(EXAMPLES[0], unreachable), None);
+        assert!(maze_best_tiles(EXAMPLES[0], unreachable).is_none());
+        assert_eq!(maze_best_tiles_string(EXAMPLES[0], unreachable), None);
+    }
+
+    // Depth first maze with some extra openings, walled like the puzzle input
+    fn generated_maze(size: usize, rng: &mut XorShift) -> String {
+        let mut maze = Grid::init(size, size, b'#');
+        let mut stack = vec![Pos::new(1, 1)];
+        *maze.pos_index_mut(Pos::new(1, 1)) = b'.';
+
+        while let Some(&position) = stack.last() {
+            let unvisited = position
+                .adjacent()
+                .map(|wall| (wall, wall + (wall - position)))
+                .filter(|&(_, cell)| {
+                    cell.x > 0
+                        && cell.y > 0
+                        && maze.pos_get(cell + Pos::new(1, 1)).is_some()
+                        && maze.pos_get(cell) == Some(&b'#')
+                })
+                .collect_vec();
+
+            if unvisited.is_empty() {
+                stack.pop();
+                continue;
+            }
+
+            let (wall, cell) = unvisited[rng.below(unvisited.len())];
+
+            *maze.pos_index_mut(wall) = b'.';
+            *maze.pos_index_mut(cell) = b'.';
+            stack.push(cell);
+        }
+
+        // Open some inner walls between cells to make loops
+        for x in 1..size - 1 {
+            for y in 1..size - 1 {
+                if (x + y) % 2 == 1 && maze[(x, y)] == b'#' && rng.below(100) < 10 {
+                    maze[(x, y)] = b'.';
+                }
+            }
+        }
+
+        maze[(size - 2, 1)] = b'S';
+        maze[(1, size - 2)] = b'E';
+
+        grid_string(&maze)
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with --release --ignored --nocapture"]
+    fn maze_graph_bench() {
+        // Generated mazes of puzzle size
+        let inputs = bench_inputs("ReindeerMaze", |rng| {
+            (0..4)
+                .map(|maze| (format!("generated maze {maze}"), generated_maze(141, rng)))
+                .collect()
+        });
+
+        for (name, input) in inputs {
+            println!("{name}");
+
+            let costs = MazeCosts::default();
+            let answers = [
+                best_of_runs("astar score", || maze_best_path_score_costs(&input, costs)),
+                best_of_runs("astar seats", || maze_best_seats_count_costs(&input, costs)),
+                best_of_runs("graph score", || {
+                    Some(MazeGraph::new(&input, costs).best_score_seats()?.0)
+                }),
+                best_of_runs("graph seats", || {
+                    Some(MazeGraph::new(&input, costs).best_score_seats()?.1)
+                }),
+            ];
+
+            assert_eq!(answers[0], answers[2]);
+            assert_eq!(answers[1], answers[3]);
+            println!();
+        }
+    }
+}